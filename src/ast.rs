@@ -0,0 +1,146 @@
+//! Syntax tree produced by the parser.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+    Boolean(bool),
+}
+
+/// How a value is printed: numbers as they are, text quoted, blobs in hex.
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Null => write!(f, "NULL"),
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::Real(value) => write!(f, "{:?}", value),
+            Literal::Text(value) => write!(f, "{:?}", value),
+            Literal::Blob(value) => {
+                let hex: String = value.iter().map(|byte| format!("{:02X}", byte)).collect();
+                write!(f, "x'{}'", hex)
+            }
+            Literal::Boolean(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    Column(String),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    /// `value BETWEEN low AND high`, inclusive at both ends.
+    Between(Box<Expr>, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// `value IS NULL`; `IS NOT NULL` is its negation.
+    IsNull(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub type_name: String,
+    /// `n` in a type written `varchar(n)`.
+    pub size: Option<usize>,
+    pub primary_key: bool,
+    pub unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndex {
+    pub name: String,
+    pub unique: bool,
+    pub table: String,
+    pub column: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    /// Explicit column list; `None` means every column in table order.
+    pub columns: Option<Vec<String>>,
+    pub values: Vec<Literal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Min,
+    Max,
+    Sum,
+    Avg,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`: every column in table order.
+    Wildcard,
+    Column(String),
+    /// An aggregate over a column; `None` stands for `count(*)`.
+    Aggregate(AggregateFunction, Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub column: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub table: String,
+    pub projection: Vec<SelectItem>,
+    pub filter: Option<Expr>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub table: String,
+    pub assignments: Vec<(String, Literal)>,
+    pub filter: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table: String,
+    pub filter: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Insert(Insert),
+    Select(Select),
+    Update(Update),
+    Delete(Delete),
+    CreateTable(CreateTable),
+    CreateIndex(CreateIndex),
+    Begin,
+    Commit,
+    Rollback,
+}
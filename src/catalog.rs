@@ -0,0 +1,332 @@
+//! Table schemas and the catalog that records them.
+//!
+//! The catalog is itself a table, rooted at the page named in the file
+//! header, with one row per table: its name, the root page of its tree and
+//! the `create table` statement that describes its columns. Rows of every
+//! table are stored in their tree as the primary key, held by the cell, and a
+//! record encoding the other columns, each as a tag byte naming the type of
+//! the value followed by its payload.
+//!
+//! An index has a row of its own in the catalog, with its `create index`
+//! statement, and a tree keyed by the indexed value and the primary key of
+//! each row.
+
+use crate::ast::{CreateTable, Literal};
+
+/// Schema of the catalog.
+pub const CATALOG_SQL: &str = "create table catalog (id integer primary key, name text, root_page integer, sql text)";
+
+/// Schema of the table a new database starts with, which the shorthand
+/// statements work on.
+pub const USERS_SQL: &str = "create table users (id integer primary key, username varchar(32), email varchar(255))";
+
+/// Columns of a catalog row read back when the database is opened.
+pub const CATALOG_ROOT_PAGE_COLUMN: usize = 2;
+pub const CATALOG_SQL_COLUMN: usize = 3;
+
+/* Record tags. Integers take the fewest bytes that hold them. */
+const TAG_NULL: u8 = 0;
+const TAG_INT8: u8 = 1;
+const TAG_INT16: u8 = 2;
+const TAG_INT32: u8 = 3;
+const TAG_INT64: u8 = 4;
+const TAG_REAL: u8 = 5;
+const TAG_FALSE: u8 = 6;
+const TAG_TRUE: u8 = 7;
+const TAG_TEXT: u8 = 8;
+const TAG_BLOB: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    Real,
+    Text,
+    Blob,
+    Boolean,
+}
+
+impl ColumnType {
+    /// The type a declared type name stands for.
+    pub fn from_name(name: &str) -> Option<ColumnType> {
+        match name {
+            "integer" | "int" => Some(ColumnType::Integer),
+            "real" | "float" | "double" => Some(ColumnType::Real),
+            "text" | "varchar" | "char" => Some(ColumnType::Text),
+            "blob" => Some(ColumnType::Blob),
+            "boolean" | "bool" => Some(ColumnType::Boolean),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` is of this type, as every non-NULL value stored in a
+    /// column of the type is.
+    pub fn is_type_of(&self, value: &Literal) -> bool {
+        matches!((self, value), (ColumnType::Integer, Literal::Integer(_)) | (ColumnType::Real, Literal::Real(_))
+            | (ColumnType::Text, Literal::Text(_)) | (ColumnType::Blob, Literal::Blob(_))
+            | (ColumnType::Boolean, Literal::Boolean(_)))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+            ColumnType::Blob => "BLOB",
+            ColumnType::Boolean => "BOOLEAN",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+    /// Longest text accepted, in bytes, as declared by `varchar(n)`.
+    pub max_length: Option<usize>,
+}
+
+pub enum SchemaError {
+    UnknownType(String),
+    DuplicateColumn(String),
+    /// No column, or more than one, is an `integer primary key`.
+    MissingKey,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    pub name: String,
+    pub root_page_num: usize,
+    pub columns: Vec<Column>,
+    /// Position of the `integer primary key` column, whose values key the tree.
+    pub key_column: usize,
+    pub indexes: Vec<Index>,
+}
+
+/// A secondary index over one column of a table: a tree keyed by the values
+/// of the column, each paired with the primary key of the row holding it.
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    pub name: String,
+    /// Position of the indexed column in the table.
+    pub column: usize,
+    /// Set when no two rows may hold the same value in the column.
+    pub unique: bool,
+    pub root_page_num: usize,
+}
+
+impl Schema {
+    pub fn from_create(create: &CreateTable, root_page_num: usize) -> Result<Schema, SchemaError> {
+        let mut columns: Vec<Column> = Vec::with_capacity(create.columns.len());
+        let mut key_columns = Vec::new();
+        for (i, def) in create.columns.iter().enumerate() {
+            let column_type = match ColumnType::from_name(&def.type_name) {
+                Some(column_type) => column_type,
+                None => return Err(SchemaError::UnknownType(def.type_name.clone())),
+            };
+            if columns.iter().any(|column| column.name == def.name) {
+                return Err(SchemaError::DuplicateColumn(def.name.clone()));
+            }
+            if def.primary_key {
+                if column_type != ColumnType::Integer {
+                    return Err(SchemaError::MissingKey);
+                }
+                key_columns.push(i);
+            }
+            columns.push(Column {
+                name: def.name.clone(),
+                column_type,
+                max_length: if column_type == ColumnType::Text { def.size } else { None },
+            });
+        }
+        if key_columns.len() != 1 {
+            return Err(SchemaError::MissingKey);
+        }
+
+        Ok(Schema {
+            name: create.name.clone(),
+            root_page_num,
+            columns,
+            key_column: key_columns[0],
+            indexes: Vec::new(),
+        })
+    }
+
+    /// The `create table` statement describing this table, as stored in the
+    /// catalog.
+    pub fn to_sql(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().enumerate().map(|(i, column)| {
+            let mut sql = match (column.column_type, column.max_length) {
+                (ColumnType::Text, Some(max_length)) => format!("{} varchar({})", column.name, max_length),
+                (column_type, _) => format!("{} {}", column.name, column_type.name().to_lowercase()),
+            };
+            if i == self.key_column {
+                sql.push_str(" primary key");
+            }
+            sql
+        }).collect();
+        format!("create table {} ({})", self.name, columns.join(", "))
+    }
+
+    pub fn key_column_name(&self) -> &str {
+        &self.columns[self.key_column].name
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|column| column.name.clone()).collect()
+    }
+
+    /// Encode every value of a row but the key, each as a tag byte and its
+    /// payload: integers in 1, 2, 4 or 8 little-endian bytes, reals as 8-byte
+    /// little-endian floats, text and blobs as a 4-byte little-endian length
+    /// followed by the bytes. NULL and the booleans are a tag alone. Values
+    /// must already have their column's type.
+    pub fn encode_record(&self, values: &[Literal]) -> Vec<u8> {
+        let mut record = Vec::new();
+        for (i, value) in values.iter().enumerate() {
+            if i != self.key_column {
+                encode_value(&mut record, value);
+            }
+        }
+        record
+    }
+
+    /// The values of a row from its key and record. Bytes past the last
+    /// column are ignored. Returns None for a record that ends early or holds
+    /// an unknown tag.
+    pub fn decode_record(&self, key: usize, record: &[u8]) -> Option<Vec<Literal>> {
+        let mut values = Vec::with_capacity(self.columns.len());
+        let mut offset = 0;
+        for i in 0..self.columns.len() {
+            if i == self.key_column {
+                values.push(Literal::Integer(key as i64));
+                continue;
+            }
+            let (value, next_offset) = decode_value(record, offset)?;
+            values.push(value);
+            offset = next_offset;
+        }
+        Some(values)
+    }
+
+    /// The `create index` statement describing one of the table's indexes,
+    /// as stored in the catalog.
+    pub fn index_sql(&self, index: &Index) -> String {
+        let kind = if index.unique { "unique index" } else { "index" };
+        format!("create {} {} on {} ({})", kind, index.name, self.name, self.columns[index.column].name)
+    }
+}
+
+/// Append a value to a record as its tag byte and payload.
+pub fn encode_value(record: &mut Vec<u8>, value: &Literal) {
+    match value {
+        Literal::Null => record.push(TAG_NULL),
+        Literal::Integer(value) => {
+            let value = *value;
+            if value == value as i8 as i64 {
+                record.push(TAG_INT8);
+                record.extend_from_slice(&(value as i8).to_le_bytes());
+            } else if value == value as i16 as i64 {
+                record.push(TAG_INT16);
+                record.extend_from_slice(&(value as i16).to_le_bytes());
+            } else if value == value as i32 as i64 {
+                record.push(TAG_INT32);
+                record.extend_from_slice(&(value as i32).to_le_bytes());
+            } else {
+                record.push(TAG_INT64);
+                record.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        Literal::Real(value) => {
+            record.push(TAG_REAL);
+            record.extend_from_slice(&value.to_le_bytes());
+        }
+        Literal::Boolean(value) => record.push(if *value { TAG_TRUE } else { TAG_FALSE }),
+        Literal::Text(value) => {
+            record.push(TAG_TEXT);
+            record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            record.extend_from_slice(value.as_bytes());
+        }
+        Literal::Blob(value) => {
+            record.push(TAG_BLOB);
+            record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            record.extend_from_slice(value);
+        }
+    }
+}
+
+/// A value as it is encoded in a record, its text or bytes left in the
+/// record, so that it can be read without being copied out.
+pub enum ValueRef<'a> {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Text(&'a [u8]),
+    Blob(&'a [u8]),
+}
+
+/// The value encoded at `offset` in a record and the offset just past it,
+/// or None if the record ends early or holds an unknown tag there.
+pub fn decode_value_ref(record: &[u8], offset: usize) -> Option<(ValueRef<'_>, usize)> {
+    let tag = *record.get(offset)?;
+    let offset = offset + 1;
+    let width = match tag {
+        TAG_NULL | TAG_FALSE | TAG_TRUE => 0,
+        TAG_INT8 => 1,
+        TAG_INT16 => 2,
+        TAG_INT32 => 4,
+        TAG_INT64 | TAG_REAL => 8,
+        TAG_TEXT | TAG_BLOB => 4 + u32::from_le_bytes(record.get(offset..offset + 4)?.try_into().unwrap()) as usize,
+        _ => return None,
+    };
+    let payload = record.get(offset..offset + width)?;
+    let value = match tag {
+        TAG_INT8 => ValueRef::Integer(i8::from_le_bytes(payload.try_into().unwrap()) as i64),
+        TAG_INT16 => ValueRef::Integer(i16::from_le_bytes(payload.try_into().unwrap()) as i64),
+        TAG_INT32 => ValueRef::Integer(i32::from_le_bytes(payload.try_into().unwrap()) as i64),
+        TAG_INT64 => ValueRef::Integer(i64::from_le_bytes(payload.try_into().unwrap())),
+        TAG_REAL => ValueRef::Real(f64::from_le_bytes(payload.try_into().unwrap())),
+        TAG_FALSE => ValueRef::Boolean(false),
+        TAG_TRUE => ValueRef::Boolean(true),
+        TAG_TEXT => ValueRef::Text(&payload[4..]),
+        TAG_BLOB => ValueRef::Blob(&payload[4..]),
+        _ => ValueRef::Null,
+    };
+    Some((value, offset + width))
+}
+
+/// The value encoded at `offset` in a record, like `decode_value_ref`, as
+/// a literal of its own.
+pub fn decode_value(record: &[u8], offset: usize) -> Option<(Literal, usize)> {
+    let (value, next_offset) = decode_value_ref(record, offset)?;
+    let value = match value {
+        ValueRef::Null => Literal::Null,
+        ValueRef::Integer(value) => Literal::Integer(value),
+        ValueRef::Real(value) => Literal::Real(value),
+        ValueRef::Boolean(value) => Literal::Boolean(value),
+        ValueRef::Text(text) => Literal::Text(String::from_utf8_lossy(text).to_string()),
+        ValueRef::Blob(blob) => Literal::Blob(blob.to_vec()),
+    };
+    Some((value, next_offset))
+}
+
+/// The value an index key holds for `value`: text and blobs are cut to
+/// their first `max_size` bytes, text at a character boundary, so that
+/// keys stay short. Values sharing such a prefix share the key value.
+pub fn index_key_value(value: &Literal, max_size: usize) -> Literal {
+    match value {
+        Literal::Text(text) if text.len() > max_size => {
+            let mut end = max_size;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            Literal::Text(text[..end].to_string())
+        }
+        Literal::Blob(blob) if blob.len() > max_size => Literal::Blob(blob[..max_size].to_vec()),
+        _ => value.clone(),
+    }
+}
@@ -0,0 +1,96 @@
+//! Reading the CSV files loaded by `.import`.
+//!
+//! A file holds a record per line, its fields separated by commas. A field
+//! enclosed in double quotes may hold commas, line breaks and quotes, the
+//! latter doubled. An empty field left unquoted stands for NULL, and blank
+//! lines are skipped.
+
+use std::mem;
+
+/// A field of a record: its text, or None for an empty unquoted field.
+pub type Field = Option<String>;
+
+pub struct Record {
+    /// Line of the file the record starts on, counting from 1.
+    pub line: usize,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug)]
+pub struct CsvError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The records of a CSV text, in file order.
+pub fn parse_records(text: &str) -> Result<Vec<Record>, CsvError> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut record_line = 1;
+    loop {
+        let field = if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err(CsvError { line: record_line, message: "unterminated quoted field".to_string() }),
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        value.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        value.push(c);
+                    }
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',') | Some('\r') | Some('\n')) {
+                return Err(CsvError { line, message: "expected a comma after a quoted field".to_string() });
+            }
+            Some(value)
+        } else {
+            let mut value = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '\r' || c == '\n' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+            if value.is_empty() { None } else { Some(value) }
+        };
+        fields.push(field);
+
+        match chars.next() {
+            Some(',') => continue,
+            Some('\r') if chars.peek() == Some(&'\n') => {
+                chars.next();
+            }
+            _ => {}
+        }
+        /* The record ends here; a blank line holds a single empty field */
+        if fields.len() > 1 || fields[0].is_some() {
+            records.push(Record { line: record_line, fields: mem::take(&mut fields) });
+        }
+        fields.clear();
+        if chars.peek().is_none() {
+            break;
+        }
+        line += 1;
+        record_line = line;
+    }
+    Ok(records)
+}
+
+/// The bytes a field of hexadecimal digits, two per byte, stands for.
+pub fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect())
+}
@@ -0,0 +1,79 @@
+//! Errors of the engine.
+//!
+//! Every fallible operation returns `Result`, and errors travel up to the
+//! REPL, which prints them and reads the next statement. An I/O error or a
+//! corrupt page met while a statement runs also rolls back the open
+//! transaction, since the statement may have stopped halfway.
+
+use std::{fmt, io};
+
+use crate::lexer::SyntaxError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the database file or its log failed.
+    Io(io::Error),
+    /// The disk, or the file size allowed to the process, is exhausted.
+    Full,
+    /// The file is not a database this version can open.
+    Format(String),
+    /// A page or record does not hold what the engine would have written.
+    Corrupt(String),
+    Syntax(SyntaxError),
+    /// A statement that does not fit the schema or the state of the session,
+    /// such as an unknown table or a `commit` outside a transaction.
+    Invalid(String),
+    /// A change that would break a constraint of the table.
+    Constraint(String),
+    NegativeKey,
+    StringTooLong,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether the error may have left a statement half done, so that the
+    /// open transaction has to be rolled back.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Io(_) | Error::Full | Error::Corrupt(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}.", err),
+            Error::Full => write!(f, "Database or disk is full."),
+            Error::Format(message) => write!(f, "{}.", message),
+            Error::Corrupt(message) => write!(f, "{}. Corrupt file.", message),
+            Error::Syntax(err) => write!(f, "{}", err),
+            Error::Invalid(message) | Error::Constraint(message) => write!(f, "{}.", message),
+            Error::NegativeKey => write!(f, "ID must be positive."),
+            Error::StringTooLong => write!(f, "String is too long."),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        match err.kind() {
+            io::ErrorKind::StorageFull | io::ErrorKind::FileTooLarge => Error::Full,
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(err: SyntaxError) -> Error {
+        Error::Syntax(err)
+    }
+}
@@ -0,0 +1,155 @@
+//! Keys of the B-trees.
+//!
+//! A tree orders its cells by key, a byte string compared by the comparator
+//! of the tree's key format. A table is keyed by its integer primary key,
+//! eight little-endian bytes. An index is keyed by a composite key: a
+//! sequence of values, each encoded like a value of a record, compared value
+//! by value, so that a key sorts right before every longer key it starts.
+
+use std::cmp::Ordering;
+
+use crate::ast::Literal;
+use crate::catalog::{decode_value, decode_value_ref, encode_value, ValueRef};
+
+/// Orders two keys of a tree.
+pub type Comparator = fn(&[u8], &[u8]) -> Ordering;
+
+/// Bytes taken by an integer key.
+pub const INTEGER_KEY_SIZE: usize = 8;
+
+/// How the keys of a tree are compared and shown.
+#[derive(Clone, Copy)]
+pub struct KeyFormat {
+    pub compare: Comparator,
+    /// The key as printed by `.btree`.
+    pub format: fn(&[u8]) -> String,
+    /// Whether every key is `INTEGER_KEY_SIZE` bytes, so that leaves store
+    /// them without a length.
+    pub fixed_size: bool,
+}
+
+/// Keys of a table's tree: primary keys, in numeric order.
+pub const INTEGER_KEYS: KeyFormat = KeyFormat {
+    compare: compare_integer_keys,
+    format: format_integer_key,
+    fixed_size: true,
+};
+
+/// Keys of an index's tree: composite keys, in the order of their values.
+pub const COMPOSITE_KEYS: KeyFormat = KeyFormat {
+    compare: compare_composite_keys,
+    format: format_composite_key,
+    fixed_size: false,
+};
+
+pub fn integer_key(key: usize) -> Vec<u8> {
+    (key as u64).to_le_bytes().to_vec()
+}
+
+/// The integer an integer key encodes. Only the first `INTEGER_KEY_SIZE`
+/// bytes are read; a shorter key reads as if padded with zeros.
+pub fn integer_key_value(key: &[u8]) -> usize {
+    let mut bytes = [0u8; INTEGER_KEY_SIZE];
+    let length = key.len().min(INTEGER_KEY_SIZE);
+    bytes[..length].copy_from_slice(&key[..length]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+pub fn compare_integer_keys(left: &[u8], right: &[u8]) -> Ordering {
+    integer_key_value(left).cmp(&integer_key_value(right))
+}
+
+fn format_integer_key(key: &[u8]) -> String {
+    integer_key_value(key).to_string()
+}
+
+pub fn composite_key(values: &[Literal]) -> Vec<u8> {
+    let mut key = Vec::new();
+    for value in values {
+        encode_value(&mut key, value);
+    }
+    key
+}
+
+/// The values of a composite key, or None for bytes that do not encode
+/// a sequence of values.
+pub fn composite_key_values(key: &[u8]) -> Option<Vec<Literal>> {
+    let mut values = Vec::new();
+    let mut offset = 0;
+    while offset < key.len() {
+        let (value, next_offset) = decode_value(key, offset)?;
+        values.push(value);
+        offset = next_offset;
+    }
+    Some(values)
+}
+
+/// Compare composite keys value by value, as `compare_literals` orders
+/// values, reading each value where it is encoded. Bytes that no longer
+/// decode as a value sort after every value and are compared as bytes, so
+/// that the order stays total on a corrupt page.
+pub fn compare_composite_keys(left: &[u8], right: &[u8]) -> Ordering {
+    let mut left_offset = 0;
+    let mut right_offset = 0;
+    loop {
+        if left_offset == left.len() || right_offset == right.len() {
+            /* A key sorts right before every longer key it starts */
+            return (left_offset < left.len()).cmp(&(right_offset < right.len()));
+        }
+        match (decode_value_ref(left, left_offset), decode_value_ref(right, right_offset)) {
+            (Some((left_value, left_next)), Some((right_value, right_next))) => {
+                let ordering = compare_value_refs(&left_value, &right_value);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                left_offset = left_next;
+                right_offset = right_next;
+            }
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => return left[left_offset..].cmp(&right[right_offset..]),
+        }
+    }
+}
+
+/// Order two encoded values as `compare_literals` orders the literals they
+/// stand for: numbers of any type by value, then by type.
+fn compare_value_refs(left: &ValueRef, right: &ValueRef) -> Ordering {
+    match (left, right) {
+        (ValueRef::Integer(left), ValueRef::Integer(right)) => left.cmp(right),
+        (ValueRef::Text(left), ValueRef::Text(right)) | (ValueRef::Blob(left), ValueRef::Blob(right)) => left.cmp(right),
+        _ => match (value_ref_number(left), value_ref_number(right)) {
+            (Some(left), Some(right)) => left.total_cmp(&right),
+            _ => value_ref_rank(left).cmp(&value_ref_rank(right)),
+        },
+    }
+}
+
+/// The number a numeric value stands for, a boolean counting as 0 or 1.
+fn value_ref_number(value: &ValueRef) -> Option<f64> {
+    match value {
+        ValueRef::Integer(value) => Some(*value as f64),
+        ValueRef::Real(value) => Some(*value),
+        ValueRef::Boolean(value) => Some(*value as i64 as f64),
+        _ => None,
+    }
+}
+
+fn value_ref_rank(value: &ValueRef) -> u8 {
+    match value {
+        ValueRef::Null => 0,
+        ValueRef::Integer(_) | ValueRef::Real(_) | ValueRef::Boolean(_) => 1,
+        ValueRef::Text(_) => 2,
+        ValueRef::Blob(_) => 3,
+    }
+}
+
+fn format_composite_key(key: &[u8]) -> String {
+    match composite_key_values(key) {
+        Some(values) => {
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            format!("({})", values.join(", "))
+        }
+        None => format!("{:?}", key),
+    }
+}
@@ -0,0 +1,259 @@
+//! Splits a line of SQL into tokens, each tagged with the position it starts
+//! at so that errors can point at it.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// An unquoted word: a keyword, an identifier, or in the shorthand
+    /// statement forms a bare value such as `person1@example.com`.
+    Word(String),
+    Integer(i64),
+    Real(f64),
+    /// A single-quoted string, with `''` standing for one quote.
+    Str(String),
+    /// `x'...'`: bytes written as pairs of hexadecimal digits.
+    Blob(Vec<u8>),
+    LeftParen,
+    RightParen,
+    Comma,
+    Semicolon,
+    Star,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Eof,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "'{}'", word),
+            TokenKind::Integer(value) => write!(f, "'{}'", value),
+            TokenKind::Real(value) => write!(f, "'{:?}'", value),
+            TokenKind::Str(value) => write!(f, "string '{}'", value.replace('\'', "''")),
+            TokenKind::Blob(value) => {
+                let hex: String = value.iter().map(|byte| format!("{:02X}", byte)).collect();
+                write!(f, "blob x'{}'", hex)
+            }
+            TokenKind::LeftParen => write!(f, "'('"),
+            TokenKind::RightParen => write!(f, "')'"),
+            TokenKind::Comma => write!(f, "','"),
+            TokenKind::Semicolon => write!(f, "';'"),
+            TokenKind::Star => write!(f, "'*'"),
+            TokenKind::Minus => write!(f, "'-'"),
+            TokenKind::Equal => write!(f, "'='"),
+            TokenKind::NotEqual => write!(f, "'!='"),
+            TokenKind::Less => write!(f, "'<'"),
+            TokenKind::LessEqual => write!(f, "'<='"),
+            TokenKind::Greater => write!(f, "'>'"),
+            TokenKind::GreaterEqual => write!(f, "'>='"),
+            TokenKind::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// 1-based character position of the first character of the token.
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Syntax error at position {}: {}.", self.position, self.message)
+    }
+}
+
+/// Characters that may appear in an unquoted word. Besides identifier
+/// characters this admits `@`, `.` and an inner `-`, so that a word such as
+/// `person1@example.com` lexes as one token.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '@' || c == '.'
+}
+
+/// Digits, a point and more digits, as in `3.25`.
+fn is_real(word: &str) -> bool {
+    match word.split_once('.') {
+        Some((whole, fraction)) => {
+            !whole.is_empty() && !fraction.is_empty()
+                && whole.chars().all(|c| c.is_ascii_digit())
+                && fraction.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// A word as a token: an integer or a real if it is one, else a word.
+fn word_token(word: String, position: usize) -> Result<TokenKind, SyntaxError> {
+    if word.chars().all(|c| c.is_ascii_digit()) {
+        return match word.parse::<i64>() {
+            Ok(value) => Ok(TokenKind::Integer(value)),
+            Err(_) => Err(SyntaxError { position, message: format!("integer {} is too large", word) }),
+        };
+    }
+    if is_real(&word) {
+        return Ok(TokenKind::Real(word.parse().unwrap()));
+    }
+    Ok(TokenKind::Word(word))
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, SyntaxError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let position = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let kind = if (c == 'x' || c == 'X') && chars.get(i + 1) == Some(&'\'') {
+            let start = i + 2;
+            i = start;
+            while i < chars.len() && chars[i] != '\'' {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(SyntaxError { position, message: "unterminated blob".to_string() });
+            }
+            let hex: String = chars[start..i].iter().collect();
+            i += 1;
+            if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(SyntaxError { position, message: format!("malformed blob x'{}'", hex) });
+            }
+            let bytes = (0..hex.len()).step_by(2).map(|j| u8::from_str_radix(&hex[j..j + 2], 16).unwrap()).collect();
+            TokenKind::Blob(bytes)
+        } else if is_word_char(c) {
+            let start = i;
+            while i < chars.len() && (is_word_char(chars[i]) || (chars[i] == '-' && i + 1 < chars.len() && is_word_char(chars[i + 1]))) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            word_token(word, position)?
+        } else if c == '\'' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                if i >= chars.len() {
+                    return Err(SyntaxError { position, message: "unterminated string".to_string() });
+                }
+                if chars[i] == '\'' {
+                    if i + 1 < chars.len() && chars[i + 1] == '\'' {
+                        value.push('\'');
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                value.push(chars[i]);
+                i += 1;
+            }
+            TokenKind::Str(value)
+        } else {
+            let next = chars.get(i + 1).copied();
+            let (kind, len) = match (c, next) {
+                ('(', _) => (TokenKind::LeftParen, 1),
+                (')', _) => (TokenKind::RightParen, 1),
+                (',', _) => (TokenKind::Comma, 1),
+                (';', _) => (TokenKind::Semicolon, 1),
+                ('*', _) => (TokenKind::Star, 1),
+                ('-', _) => (TokenKind::Minus, 1),
+                ('=', _) => (TokenKind::Equal, 1),
+                ('!', Some('=')) => (TokenKind::NotEqual, 2),
+                ('<', Some('>')) => (TokenKind::NotEqual, 2),
+                ('<', Some('=')) => (TokenKind::LessEqual, 2),
+                ('<', _) => (TokenKind::Less, 1),
+                ('>', Some('=')) => (TokenKind::GreaterEqual, 2),
+                ('>', _) => (TokenKind::Greater, 1),
+                _ => {
+                    return Err(SyntaxError { position, message: format!("unexpected character '{}'", c) });
+                }
+            };
+            i += len;
+            kind
+        };
+        tokens.push(Token { kind, position });
+    }
+
+    tokens.push(Token { kind: TokenKind::Eof, position: chars.len() + 1 });
+    Ok(tokens)
+}
+
+/// Split a shorthand statement, such as `insert 1 user1 a@example.com` or
+/// `update 1 set email=a@example.com`, into tokens. As when the REPL read
+/// these with a pattern, each value is a run of any characters but
+/// whitespace: a number, or else a word taken as text. In the assignments
+/// after the `set` of an `update` the column name ends at the first `=`,
+/// and a comma ending a value separates it from the next assignment. A
+/// semicolon ending the line ends the statement.
+pub fn tokenize_shorthand(input: &str) -> Result<Vec<Token>, SyntaxError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let is_update = input.split_whitespace().next().is_some_and(|word| word.eq_ignore_ascii_case("update"));
+    let mut assignments = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        let mut end = i;
+        let mut separator = None;
+        if end == chars.len() && chars[end - 1] == ';' && end - 1 > start {
+            end -= 1;
+            separator = Some(Token { kind: TokenKind::Semicolon, position: end + 1 });
+        } else if assignments && chars[end - 1] == ',' && end - 1 > start {
+            end -= 1;
+            separator = Some(Token { kind: TokenKind::Comma, position: end + 1 });
+        }
+
+        let mut value_start = start;
+        if assignments {
+            if let Some(equal) = chars[start..end].iter().position(|&c| c == '=') {
+                tokens.push(Token { kind: TokenKind::Word(chars[start..start + equal].iter().collect()), position: start + 1 });
+                tokens.push(Token { kind: TokenKind::Equal, position: start + equal + 1 });
+                value_start = start + equal + 1;
+            }
+        }
+        if value_start < end {
+            let mut position = value_start + 1;
+            let mut word: String = chars[value_start..end].iter().collect();
+            if let Some(number) = word.strip_prefix('-').filter(|number| number.starts_with(|c: char| c.is_ascii_digit())) {
+                if !matches!(word_token(number.to_string(), position + 1)?, TokenKind::Word(_)) {
+                    tokens.push(Token { kind: TokenKind::Minus, position });
+                    word = number.to_string();
+                    position += 1;
+                }
+            }
+            if is_update && word.eq_ignore_ascii_case("set") {
+                assignments = true;
+            }
+            tokens.push(Token { kind: word_token(word, position)?, position });
+        }
+        tokens.extend(separator);
+    }
+
+    tokens.push(Token { kind: TokenKind::Eof, position: chars.len() + 1 });
+    Ok(tokens)
+}
@@ -0,0 +1,3716 @@
+//! A small embedded SQL database.
+//!
+//! ```no_run
+//! use db_simple::{Database, Value};
+//!
+//! let mut database = Database::open("your_dbname.db")?;
+//! let mut connection = database.connect();
+//! connection.execute("insert 1 user1 person1@example.com")?;
+//! for row in connection.execute("select username from users where id = 1")? {
+//!     assert_eq!(row, vec![Value::Text("user1".to_string())]);
+//! }
+//! database.close()?;
+//! # Ok::<(), db_simple::Error>(())
+//! ```
+
+mod ast;
+mod catalog;
+mod csv;
+mod error;
+mod key;
+mod lexer;
+mod parser;
+mod planner;
+mod wal;
+
+pub use crate::ast::Literal as Value;
+pub use crate::error::{Error, Result};
+pub use crate::lexer::SyntaxError;
+pub use crate::parser::DEFAULT_TABLE;
+
+use std::mem;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::Path;
+use std::slice;
+
+use crate::NodeType::{NodeInternal, NodeLeaf};
+use crate::StatementType::{StatementBegin, StatementCommit, StatementCreateIndex, StatementCreateTable, StatementDelete, StatementInsert, StatementNone, StatementRollback, StatementSelect, StatementUpdate};
+use crate::ast::{AggregateFunction, CompareOp, CreateIndex, CreateTable, Delete, Expr, Insert, Literal, OrderBy, Select, SelectItem, Update};
+use crate::catalog::{ColumnType, Index, Schema, SchemaError};
+use crate::key::{Comparator, KeyFormat};
+use crate::planner::{Aggregate, KeyRange};
+use crate::wal::Wal;
+
+#[allow(clippy::enum_variant_names, reason = "the variants are glob-imported, and the prefix names their enum at each use")]
+enum StatementType {
+    StatementInsert,
+    StatementSelect,
+    StatementDelete,
+    StatementUpdate,
+    StatementCreateTable,
+    StatementCreateIndex,
+    StatementBegin,
+    StatementCommit,
+    StatementRollback,
+    StatementNone,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum NodeType {
+    NodeInternal,
+    NodeLeaf,
+}
+
+/// The node type stored as `tag`, or None for a byte no node type uses.
+fn node_type_from_tag(tag: u8) -> Option<NodeType> {
+    match tag {
+        NODE_TYPE_INTERNAL => Some(NodeInternal),
+        NODE_TYPE_LEAF | NODE_TYPE_LEAF_BYTE_KEYS => Some(NodeLeaf),
+        _ => None,
+    }
+}
+
+/// Fail if a walk down a tree reached `page_num` `depth` levels below the
+/// root, deeper than any tree of a sound file.
+fn check_tree_depth(page_num: usize, depth: usize) -> Result<()> {
+    if depth >= MAX_TREE_DEPTH {
+        return Err(Error::Corrupt(format!("Page {}: tree deeper than {} levels", page_num, MAX_TREE_DEPTH)));
+    }
+    Ok(())
+}
+
+struct Statement {
+    kind: StatementType,
+    /// Table the statement works on, or for `create table` the table to create.
+    schema: Schema,
+    /// Index built by a `create index`.
+    index: Index,
+    row_to_insert: Row,
+    /// Primary key addressed by `delete` and `update`.
+    target_key: usize,
+    /// Columns set by an `update`, by position.
+    assignments: Vec<(usize, Literal)>,
+    /// Columns printed by a `select`.
+    projection: Vec<String>,
+    /// Aggregates printed by a `select`, in place of `projection`.
+    aggregates: Vec<(AggregateFunction, Option<String>)>,
+    /// `where` predicate of a `select`.
+    filter: Option<Expr>,
+    order_by: Vec<OrderBy>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+/// A row of some table: its key and the values of every column, the key
+/// column included, in table order.
+struct Row {
+    key: usize,
+    values: Vec<Literal>,
+}
+
+impl Row {
+    /// Value of a column named in an expression.
+    fn column_value(&self, schema: &Schema, column: &str) -> Literal {
+        self.values[schema.column_index(column).unwrap()].clone()
+    }
+}
+
+/// Size of the pages of a new database unless another is asked for.
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+/// Smallest page size, which still fits four leaf cells holding a short key
+/// and record each.
+pub const MIN_PAGE_SIZE: usize = 512;
+/// Largest page size, the most a 2-byte cell offset can address.
+pub const MAX_PAGE_SIZE: usize = 65536;
+
+/// Whether a database may be made of pages of this size: a power of two
+/// from `MIN_PAGE_SIZE` to `MAX_PAGE_SIZE`.
+fn valid_page_size(page_size: usize) -> bool {
+    (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) && page_size.is_power_of_two()
+}
+
+/*
+ * Node pages store every integer as a fixed-width little-endian value, and the
+ * node type and root flag as single bytes, so that a file reads the same on
+ * any platform.
+ */
+const NODE_TYPE_INTERNAL: u8 = 0;
+const NODE_TYPE_LEAF: u8 = 1;
+/// A leaf whose keys are byte strings of any length rather than integers.
+const NODE_TYPE_LEAF_BYTE_KEYS: u8 = 2;
+
+const NODE_TYPE_SIZE:usize = mem::size_of::<u8>();
+const NODE_TYPE_OFFSET:usize = 0;
+const IS_ROOT_SIZE:usize = mem::size_of::<u8>();
+const IS_ROOT_OFFSET:usize = NODE_TYPE_SIZE;
+const PARENT_POINTER_SIZE:usize = mem::size_of::<u64>();
+const PARENT_POINTER_OFFSET:usize = IS_ROOT_OFFSET + IS_ROOT_SIZE;
+const COMMON_NODE_HEADER_SIZE:usize = NODE_TYPE_SIZE + IS_ROOT_SIZE + PARENT_POINTER_SIZE;
+
+/*
+ * Leaf Node Header Layout
+*/
+const LEAF_NODE_NUM_CELLS_SIZE:usize = mem::size_of::<u64>();
+const LEAF_NODE_NUM_CELLS_OFFSET:usize = COMMON_NODE_HEADER_SIZE;
+const LEAF_NODE_NEXT_LEAF_SIZE: usize = mem::size_of::<u64>();
+const LEAF_NODE_NEXT_LEAF_OFFSET: usize = LEAF_NODE_NUM_CELLS_OFFSET + LEAF_NODE_NUM_CELLS_SIZE;
+const LEAF_NODE_CONTENT_START_SIZE: usize = mem::size_of::<u64>();
+const LEAF_NODE_CONTENT_START_OFFSET: usize = LEAF_NODE_NEXT_LEAF_OFFSET + LEAF_NODE_NEXT_LEAF_SIZE;
+const LEAF_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + LEAF_NODE_NUM_CELLS_SIZE + LEAF_NODE_NEXT_LEAF_SIZE
+    + LEAF_NODE_CONTENT_START_SIZE;
+/*
+ * Leaf Node Body Layout
+ *
+ * A slotted page: after the header, an array of 2-byte little-endian cell
+ * offsets in key order grows towards the end of the page, while the cells
+ * themselves are packed downwards from the end. Each cell is the key, the
+ * length of the record and the record, or for a record too long to keep
+ * whole (see `leaf_max_local`) its first bytes followed by the number of the
+ * overflow page holding the rest. In a leaf of integer keys each key takes 8
+ * bytes; in a leaf of byte-string keys it is a 2-byte length followed by the
+ * bytes. A removed cell leaves a hole that is only reclaimed by compacting
+ * the page when a new cell does not fit the gap between the two.
+ */
+const LEAF_NODE_SLOT_SIZE: usize = mem::size_of::<u16>();
+const LEAF_NODE_KEY_SIZE:usize = key::INTEGER_KEY_SIZE;
+const LEAF_NODE_KEY_LENGTH_SIZE: usize = mem::size_of::<u16>();
+const LEAF_NODE_RECORD_LENGTH_SIZE: usize = mem::size_of::<u32>();
+const LEAF_NODE_OVERFLOW_PAGE_SIZE: usize = mem::size_of::<u64>();
+
+/// Internal Node Header Layout
+const INTERNAL_NODE_NUM_KEYS_SIZE: usize = mem::size_of::<u64>();
+const INTERNAL_NODE_NUM_KEYS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
+const INTERNAL_NODE_RIGHT_CHILD_SIZE: usize = mem::size_of::<u64>();
+const INTERNAL_NODE_RIGHT_CHILD_OFFSET: usize = INTERNAL_NODE_NUM_KEYS_OFFSET + INTERNAL_NODE_NUM_KEYS_SIZE;
+const INTERNAL_NODE_CONTENT_START_SIZE: usize = mem::size_of::<u64>();
+const INTERNAL_NODE_CONTENT_START_OFFSET: usize = INTERNAL_NODE_RIGHT_CHILD_OFFSET + INTERNAL_NODE_RIGHT_CHILD_SIZE;
+const INTERNAL_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + INTERNAL_NODE_NUM_KEYS_SIZE + INTERNAL_NODE_RIGHT_CHILD_SIZE
+    + INTERNAL_NODE_CONTENT_START_SIZE;
+
+/*
+ * Internal Node Body Layout
+ *
+ * Slotted like a leaf. Each cell is a child page number followed by the
+ * largest key under that child, as a 2-byte length and the bytes. The right
+ * child, holding the keys past the last cell, is kept in the header.
+ */
+const INTERNAL_NODE_SLOT_SIZE: usize = mem::size_of::<u16>();
+const INTERNAL_NODE_CHILD_SIZE: usize = mem::size_of::<u64>();
+const INTERNAL_NODE_KEY_LENGTH_SIZE: usize = mem::size_of::<u16>();
+const INTERNAL_NODE_KEY_OFFSET: usize = INTERNAL_NODE_CHILD_SIZE + INTERNAL_NODE_KEY_LENGTH_SIZE;
+
+/// Bytes an index key takes besides its value's bytes: the value's tag and
+/// length, and the primary key after it.
+const INDEX_KEY_OVERHEAD: usize = 14;
+
+const INVALID_PAGE_NUM:usize = usize::MAX;
+
+/*
+ * File Header Layout, page 0. Integers are little-endian.
+ */
+const HEADER_MAGIC: &[u8; HEADER_MAGIC_SIZE] = b"db_simple format";
+const HEADER_FORMAT_VERSION: u32 = 7;
+/// Last format version whose node and free-list pages held raw native
+/// `usize` values. Such files are migrated when opened.
+const NATIVE_FORMAT_VERSION: u32 = 5;
+/// Last format version whose internal nodes held 8-byte integer keys in
+/// fixed-size cells, and whose indexes were keyed by a hash of the indexed
+/// value. Such files are migrated when opened.
+const INTEGER_KEYS_FORMAT_VERSION: u32 = 6;
+const HEADER_PAGE_NUM: usize = 0;
+const HEADER_MAGIC_SIZE: usize = 16;
+const HEADER_MAGIC_OFFSET: usize = 0;
+const HEADER_FORMAT_VERSION_SIZE: usize = mem::size_of::<u32>();
+const HEADER_FORMAT_VERSION_OFFSET: usize = HEADER_MAGIC_OFFSET + HEADER_MAGIC_SIZE;
+const HEADER_PAGE_SIZE_SIZE: usize = mem::size_of::<u32>();
+const HEADER_PAGE_SIZE_OFFSET: usize = HEADER_FORMAT_VERSION_OFFSET + HEADER_FORMAT_VERSION_SIZE;
+const HEADER_ROOT_PAGE_SIZE: usize = mem::size_of::<u64>();
+const HEADER_ROOT_PAGE_OFFSET: usize = HEADER_PAGE_SIZE_OFFSET + HEADER_PAGE_SIZE_SIZE;
+const HEADER_PAGE_COUNT_SIZE: usize = mem::size_of::<u64>();
+const HEADER_PAGE_COUNT_OFFSET: usize = HEADER_ROOT_PAGE_OFFSET + HEADER_ROOT_PAGE_SIZE;
+const HEADER_FREE_LIST_HEAD_OFFSET: usize = HEADER_PAGE_COUNT_OFFSET + HEADER_PAGE_COUNT_SIZE;
+
+/*
+ * Free List Trunk Page Layout
+ */
+const FREE_TRUNK_NEXT_SIZE: usize = mem::size_of::<u64>();
+const FREE_TRUNK_NEXT_OFFSET: usize = 0;
+const FREE_TRUNK_NUM_LEAVES_SIZE: usize = mem::size_of::<u64>();
+const FREE_TRUNK_NUM_LEAVES_OFFSET: usize = FREE_TRUNK_NEXT_OFFSET + FREE_TRUNK_NEXT_SIZE;
+const FREE_TRUNK_HEADER_SIZE: usize = FREE_TRUNK_NEXT_SIZE + FREE_TRUNK_NUM_LEAVES_SIZE;
+const FREE_TRUNK_LEAF_SIZE: usize = mem::size_of::<u64>();
+
+/*
+ * Overflow Page Layout: the next page of the chain, or 0 for the last, and
+ * as much of the record as fits.
+ */
+const OVERFLOW_NEXT_SIZE: usize = mem::size_of::<u64>();
+const OVERFLOW_NEXT_OFFSET: usize = 0;
+const OVERFLOW_DATA_OFFSET: usize = OVERFLOW_NEXT_OFFSET + OVERFLOW_NEXT_SIZE;
+
+/// Most levels a tree may have. Every internal node below the root has at
+/// least two children, so a tree this deep would take more pages than a file
+/// can address: a walk down a tree that goes deeper has followed a child
+/// pointer back up the tree.
+const MAX_TREE_DEPTH: usize = 64;
+
+/// The sizes within a page that follow from the page size, which is chosen
+/// when a database is created and recorded in its header.
+#[derive(Clone, Copy)]
+struct Layout {
+    page_size: usize,
+    leaf_node_space_for_cells: usize,
+    /// Largest leaf cell. With its slot it takes at most a quarter of a
+    /// leaf, so a leaf always has room for four.
+    leaf_node_max_cell_size: usize,
+    /// A leaf whose cells and slots take less than this is merged with or
+    /// refilled from a sibling.
+    leaf_node_min_fill: usize,
+    internal_node_space_for_cells: usize,
+    /// An internal node other than the root whose cells and slots take less
+    /// than this is merged with or refilled from a sibling.
+    internal_node_min_fill: usize,
+    /// Longest key a tree holds: half the largest leaf cell, which leaves
+    /// room for a record beside it, and for six keys in an internal node.
+    max_key_size: usize,
+    /// Longest text or blob kept whole in an index key.
+    index_key_max_value_size: usize,
+    free_trunk_max_leaves: usize,
+    overflow_data_size: usize,
+}
+
+impl Layout {
+    fn new(page_size: usize) -> Layout {
+        let leaf_node_space_for_cells = page_size - LEAF_NODE_HEADER_SIZE;
+        let leaf_node_max_cell_size = leaf_node_space_for_cells / 4 - LEAF_NODE_SLOT_SIZE;
+        let internal_node_space_for_cells = page_size - INTERNAL_NODE_HEADER_SIZE;
+        let max_key_size = leaf_node_max_cell_size / 2;
+        Layout {
+            page_size,
+            leaf_node_space_for_cells,
+            leaf_node_max_cell_size,
+            leaf_node_min_fill: leaf_node_space_for_cells / 2,
+            internal_node_space_for_cells,
+            internal_node_min_fill: internal_node_space_for_cells / 2,
+            max_key_size,
+            index_key_max_value_size: max_key_size - INDEX_KEY_OVERHEAD,
+            free_trunk_max_leaves: (page_size - FREE_TRUNK_HEADER_SIZE) / FREE_TRUNK_LEAF_SIZE,
+            overflow_data_size: page_size - OVERFLOW_DATA_OFFSET,
+        }
+    }
+
+    /// Longest record kept whole in a leaf cell whose key takes `key_area`
+    /// bytes, the length of a byte-string key included. Longer records spill
+    /// to overflow pages, so that no cell is larger than
+    /// `leaf_node_max_cell_size`. Keys are limited, and checked when a leaf
+    /// is read, so that this much always leaves room for the record length
+    /// and an overflow page.
+    fn leaf_max_local(&self, key_area: usize) -> usize {
+        self.leaf_node_max_cell_size.checked_sub(key_area + LEAF_NODE_RECORD_LENGTH_SIZE + LEAF_NODE_OVERFLOW_PAGE_SIZE)
+            .expect("leaf key larger than a cell")
+    }
+
+    /// Bytes taken by a leaf cell whose key takes `key_area` bytes, holding a
+    /// record of this length.
+    fn leaf_cell_size(&self, key_area: usize, record_length: usize) -> usize {
+        let max_local = self.leaf_max_local(key_area);
+        if record_length > max_local {
+            return key_area + LEAF_NODE_RECORD_LENGTH_SIZE + max_local + LEAF_NODE_OVERFLOW_PAGE_SIZE;
+        }
+        key_area + LEAF_NODE_RECORD_LENGTH_SIZE + record_length
+    }
+}
+
+/// How many of `cells` go to the left of two leaves sharing them, so that
+/// each takes about half of their bytes and neither is empty.
+fn leaf_split_point(cells: &[Vec<u8>]) -> usize {
+    let total: usize = cells.iter().map(|cell| cell.len() + LEAF_NODE_SLOT_SIZE).sum();
+    let mut left = 0;
+    let mut count = 0;
+    for cell in cells {
+        if 2 * (left + cell.len() + LEAF_NODE_SLOT_SIZE) > total {
+            break;
+        }
+        left += cell.len() + LEAF_NODE_SLOT_SIZE;
+        count += 1;
+    }
+    count.clamp(1, cells.len() - 1)
+}
+
+/// Bytes taken by an internal node cell holding this key.
+fn internal_cell_size(key: &[u8]) -> usize {
+    INTERNAL_NODE_KEY_OFFSET + key.len()
+}
+
+/// Bytes taken by the cells and slots of an internal node holding `keys`.
+fn internal_cells_size(keys: &[Vec<u8>]) -> usize {
+    keys.iter().map(|key| internal_cell_size(key) + INTERNAL_NODE_SLOT_SIZE).sum()
+}
+
+/// Which of `keys` moves up to the parent when an internal node holding
+/// them splits, so that each half takes about half of their bytes and
+/// neither is left without a key.
+fn internal_split_point(keys: &[Vec<u8>]) -> usize {
+    let total = internal_cells_size(keys);
+    let mut left = 0;
+    let mut middle = 0;
+    for key in keys {
+        left += internal_cell_size(key) + INTERNAL_NODE_SLOT_SIZE;
+        if 2 * left > total {
+            break;
+        }
+        middle += 1;
+    }
+    middle.clamp(1, keys.len() - 2)
+}
+
+/// Share of a node's space a bulk load fills, leaving room for a few inserts
+/// before the node splits.
+const BULK_LOAD_FILL_PERCENT: usize = 90;
+
+/// How a bulk load shares `cells`, in key order, among leaves: each range
+/// fills a leaf up to `fill_percent` of its space. A last leaf left below
+/// the minimum fill is merged with the one before it, or takes half of
+/// their cells.
+fn bulk_leaf_groups(cells: &[Vec<u8>], layout: &Layout, fill_percent: usize) -> Vec<Range<usize>> {
+    let limit = layout.leaf_node_space_for_cells * fill_percent / 100;
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (i, cell) in cells.iter().enumerate() {
+        let size = cell.len() + LEAF_NODE_SLOT_SIZE;
+        if i > start && used + size > limit {
+            groups.push(start..i);
+            start = i;
+            used = 0;
+        }
+        used += size;
+    }
+    groups.push(start..cells.len());
+
+    if groups.len() > 1 && used < layout.leaf_node_min_fill {
+        let last = groups.pop().unwrap();
+        let previous = groups.pop().unwrap();
+        let shared = &cells[previous.start..last.end];
+        if shared.iter().map(|cell| cell.len() + LEAF_NODE_SLOT_SIZE).sum::<usize>() <= layout.leaf_node_space_for_cells {
+            groups.push(previous.start..last.end);
+        } else {
+            let middle = previous.start + leaf_split_point(shared);
+            groups.push(previous.start..middle);
+            groups.push(middle..last.end);
+        }
+    }
+    groups
+}
+
+/// How a bulk load shares children, given by the largest key under each,
+/// among the internal nodes of a level: each range of at least two children
+/// fills a node up to `fill_percent` of its space with the keys of all but
+/// its last child, whose key goes up a level. A last node left below the
+/// minimum fill is merged with or refilled from the one before it.
+fn bulk_internal_groups(keys: &[Vec<u8>], layout: &Layout, fill_percent: usize) -> Vec<Range<usize>> {
+    let limit = layout.internal_node_space_for_cells * fill_percent / 100;
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for i in 1..keys.len() {
+        let size = internal_cell_size(&keys[i - 1]) + INTERNAL_NODE_SLOT_SIZE;
+        if i - 1 > start && used + size > limit {
+            groups.push(start..i);
+            start = i;
+            used = 0;
+            continue;
+        }
+        used += size;
+    }
+    groups.push(start..keys.len());
+
+    if groups.len() > 1 && used < layout.internal_node_min_fill {
+        let last = groups.pop().unwrap();
+        let previous = groups.pop().unwrap();
+        let shared = &keys[previous.start..last.end - 1];
+        if internal_cells_size(shared) <= layout.internal_node_space_for_cells {
+            groups.push(previous.start..last.end);
+        } else {
+            let middle = previous.start + internal_split_point(shared) + 1;
+            groups.push(previous.start..middle);
+            groups.push(middle..last.end);
+        }
+    }
+    groups
+}
+
+#[derive(Clone)]
+struct Page(Box<[u8]>);
+
+impl Page {
+    fn new(page_size: usize) -> Self {
+        Self (vec![0u8; page_size].into_boxed_slice())
+    }
+
+    fn page_size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::new(self.page_size())
+    }
+
+    /// Whether this leaf holds byte-string keys, each after its length,
+    /// rather than integer keys.
+    fn leaf_node_has_byte_keys(&self) -> bool {
+        self.0[NODE_TYPE_OFFSET] == NODE_TYPE_LEAF_BYTE_KEYS
+    }
+
+    /// Bytes taken by the key that starts a leaf cell, its length included.
+    fn leaf_node_key_area(&self, cell_num: usize) -> usize {
+        if self.leaf_node_has_byte_keys() {
+            return LEAF_NODE_KEY_LENGTH_SIZE + self.read_u16(self.leaf_node_cell_offset(cell_num)) as usize;
+        }
+        LEAF_NODE_KEY_SIZE
+    }
+
+    /// Length of the record of a leaf cell, overflow included.
+    fn leaf_node_record_length(&self, cell_num: usize) -> usize {
+        self.read_u32(self.leaf_node_cell_offset(cell_num) + self.leaf_node_key_area(cell_num)) as usize
+    }
+
+    /// The part of a cell's record stored in the leaf.
+    fn leaf_node_local_record(&self, cell_num: usize) -> &[u8] {
+        let key_area = self.leaf_node_key_area(cell_num);
+        let offset = self.leaf_node_cell_offset(cell_num) + key_area + LEAF_NODE_RECORD_LENGTH_SIZE;
+        let length = self.leaf_node_record_length(cell_num).min(self.layout().leaf_max_local(key_area));
+        &self.0[offset..offset + length]
+    }
+
+    /// The first overflow page of a cell's record, or 0 if it has none.
+    fn leaf_node_overflow_page(&self, cell_num: usize) -> usize {
+        let key_area = self.leaf_node_key_area(cell_num);
+        let max_local = self.layout().leaf_max_local(key_area);
+        if self.leaf_node_record_length(cell_num) <= max_local {
+            return 0;
+        }
+        self.read_u64(self.leaf_node_cell_offset(cell_num) + key_area + LEAF_NODE_RECORD_LENGTH_SIZE + max_local) as usize
+    }
+
+    fn leaf_node_cell_size(&self, cell_num: usize) -> usize {
+        self.layout().leaf_cell_size(self.leaf_node_key_area(cell_num), self.leaf_node_record_length(cell_num))
+    }
+
+    /// Whether a cell of this size fits in the leaf, once it is compacted
+    /// if need be.
+    fn leaf_node_fits(&self, cell_size: usize) -> bool {
+        self.leaf_node_used_space() + LEAF_NODE_SLOT_SIZE + cell_size <= self.layout().leaf_node_space_for_cells
+    }
+
+    /// Bytes taken by the leaf's slots and live cells, leaving out holes.
+    fn leaf_node_used_space(&self) -> usize {
+        (0..self.leaf_node_num_cells())
+            .map(|i| LEAF_NODE_SLOT_SIZE + self.leaf_node_cell_size(i))
+            .sum()
+    }
+
+    fn is_leaf_node(&self) -> bool {
+        match self.get_node_type() {
+            NodeInternal => false,
+            NodeLeaf => true,
+        }
+    }
+
+    fn get_leaf_node_next_leaf(&self) -> usize {
+        self.read_u64(LEAF_NODE_NEXT_LEAF_OFFSET) as usize
+    }
+
+    fn set_leaf_node_num_cells(&mut self, num_cells: usize) {
+        self.write_u64(LEAF_NODE_NUM_CELLS_OFFSET, num_cells as u64);
+    }
+
+    fn set_leaf_node_next_leaf(&mut self, next_leaf: usize) {
+        self.write_u64(LEAF_NODE_NEXT_LEAF_OFFSET, next_leaf as u64);
+    }
+
+    fn get_node_parent(&self) -> usize {
+        self.read_u64(PARENT_POINTER_OFFSET) as usize
+    }
+
+    fn set_node_parent(&mut self, parent_page_num: usize) {
+        self.write_u64(PARENT_POINTER_OFFSET, parent_page_num as u64);
+    }
+
+    /// Return the index of the child which should contain the given key.
+    fn internal_node_find_child(&self, key: &[u8], compare: Comparator) -> usize {
+        let num_keys = self.get_internal_node_num_keys();
+        // binary search
+        let (mut min_cell, mut max_cell) = (0, num_keys);
+        while min_cell < max_cell {
+            let cell_num = (max_cell - min_cell) / 2 + min_cell;
+            let cell_key_value = self.get_internal_node_key(cell_num);
+            if compare(cell_key_value, key) != Ordering::Less {
+                max_cell = cell_num;
+            } else {
+                min_cell = cell_num + 1;
+            }
+        }
+        max_cell
+    }
+
+    fn leaf_node_find(&self, key: &[u8], compare: Comparator) -> usize {
+        let num_cells = self.leaf_node_num_cells();
+        let (mut min_index, mut one_past_max_index) = (0, num_cells);
+        while one_past_max_index != min_index {
+            let index = (one_past_max_index + min_index) / 2;
+            let key_at_index = self.leaf_node_key(index);
+            match compare(key_at_index, key) {
+                Ordering::Equal => return index,
+                Ordering::Greater => one_past_max_index = index,
+                Ordering::Less => min_index = index + 1,
+            }
+        }
+        min_index
+    }
+
+    fn leaf_node_num_cells(&self) -> usize {
+        self.read_u64(LEAF_NODE_NUM_CELLS_OFFSET) as usize
+    }
+
+    /// Offset within the page of the start of the cell content area.
+    fn leaf_node_content_start(&self) -> usize {
+        self.read_u64(LEAF_NODE_CONTENT_START_OFFSET) as usize
+    }
+
+    fn set_leaf_node_content_start(&mut self, offset: usize) {
+        self.write_u64(LEAF_NODE_CONTENT_START_OFFSET, offset as u64);
+    }
+
+    fn leaf_node_slot_offset(cell_num: usize) -> usize {
+        LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_SLOT_SIZE
+    }
+
+    /// Offset within the page of a cell, read from its slot.
+    fn leaf_node_cell_offset(&self, cell_num: usize) -> usize {
+        self.read_u16(Page::leaf_node_slot_offset(cell_num)) as usize
+    }
+
+    fn leaf_node_cell_bytes(&self, cell_num: usize) -> Vec<u8> {
+        let offset = self.leaf_node_cell_offset(cell_num);
+        self.0[offset..offset + self.leaf_node_cell_size(cell_num)].to_vec()
+    }
+
+    fn leaf_node_cells(&self) -> Vec<Vec<u8>> {
+        (0..self.leaf_node_num_cells()).map(|i| self.leaf_node_cell_bytes(i)).collect()
+    }
+
+    /// Replace every cell of this leaf node, packing them against the end of
+    /// the page.
+    fn set_leaf_node_cells(&mut self, cells: &[Vec<u8>]) {
+        let mut content_start = self.page_size();
+        for (i, cell) in cells.iter().enumerate() {
+            content_start -= cell.len();
+            self.0[content_start..content_start + cell.len()].copy_from_slice(cell);
+            self.write_u16(Page::leaf_node_slot_offset(i), content_start as u16);
+        }
+        self.set_leaf_node_content_start(content_start);
+        self.set_leaf_node_num_cells(cells.len());
+    }
+
+    /// Rewrite the cells without the holes left by removed ones.
+    fn leaf_node_compact(&mut self) {
+        let cells = self.leaf_node_cells();
+        self.set_leaf_node_cells(&cells);
+    }
+
+    /// Insert a cell at `cell_num`, shifting the slots after it to the right.
+    /// The cell must fit, which `leaf_node_fits` tells.
+    fn leaf_node_insert_cell(&mut self, cell_num: usize, cell: &[u8]) {
+        let num_cells = self.leaf_node_num_cells();
+        let slots_end = Page::leaf_node_slot_offset(num_cells + 1);
+        if self.leaf_node_content_start() < slots_end + cell.len() {
+            self.leaf_node_compact();
+        }
+        let content_start = self.leaf_node_content_start() - cell.len();
+        self.0[content_start..content_start + cell.len()].copy_from_slice(cell);
+        self.set_leaf_node_content_start(content_start);
+
+        let start = Page::leaf_node_slot_offset(cell_num);
+        let end = Page::leaf_node_slot_offset(num_cells);
+        self.0.copy_within(start..end, start + LEAF_NODE_SLOT_SIZE);
+        self.write_u16(start, content_start as u16);
+        self.set_leaf_node_num_cells(num_cells + 1);
+    }
+
+    /// Remove a cell, shifting the slots after it to the left. The cell's
+    /// bytes are left as a hole until the page is compacted, unless they
+    /// start the content area.
+    fn leaf_node_remove_cell(&mut self, cell_num: usize) {
+        let num_cells = self.leaf_node_num_cells();
+        let offset = self.leaf_node_cell_offset(cell_num);
+        if offset == self.leaf_node_content_start() {
+            self.set_leaf_node_content_start(offset + self.leaf_node_cell_size(cell_num));
+        }
+        let start = Page::leaf_node_slot_offset(cell_num + 1);
+        let end = Page::leaf_node_slot_offset(num_cells);
+        self.0.copy_within(start..end, start - LEAF_NODE_SLOT_SIZE);
+        self.set_leaf_node_num_cells(num_cells - 1);
+    }
+
+    fn leaf_node_key(&self, cell_num: usize) -> &[u8] {
+        let offset = self.leaf_node_cell_offset(cell_num);
+        let key_area = self.leaf_node_key_area(cell_num);
+        if self.leaf_node_has_byte_keys() {
+            return &self.0[offset + LEAF_NODE_KEY_LENGTH_SIZE..offset + key_area];
+        }
+        &self.0[offset..offset + key_area]
+    }
+
+    /// The key of a cell of a table's leaf: a primary key.
+    fn leaf_node_integer_key(&self, cell_num: usize) -> usize {
+        key::integer_key_value(self.leaf_node_key(cell_num))
+    }
+
+    /// Make this page an empty leaf, holding keys of the given format.
+    fn initialize_leaf_node(&mut self, format: KeyFormat) {
+        self.0[NODE_TYPE_OFFSET] = if format.fixed_size { NODE_TYPE_LEAF } else { NODE_TYPE_LEAF_BYTE_KEYS };
+        self.set_node_root(false);
+        self.set_leaf_node_next_leaf(0);
+        self.set_leaf_node_num_cells(0);
+        self.set_leaf_node_content_start(self.page_size());
+    }
+
+    fn initialize_internal_node(&mut self) {
+        self.set_node_type(NodeInternal);
+        self.set_node_root(false);
+        self.set_internal_node_right_child(INVALID_PAGE_NUM);
+        self.set_internal_node_num_keys(0);
+        self.set_internal_node_content_start(self.page_size());
+    }
+
+    /// The type of this node. Only valid on a page that passed `check_node`
+    /// or was initialized as a node.
+    fn get_node_type(&self) -> NodeType {
+        node_type_from_tag(self.0[NODE_TYPE_OFFSET]).expect("node type checked by check_node")
+    }
+
+    /// Check that this page holds a well-formed node: a known type tag, a
+    /// root flag of 0 or 1, and counts, cell offsets and lengths that stay
+    /// inside the page. The accessors trust these fields, so a page read
+    /// from the file is checked before the tree uses it.
+    fn check_node(&self, page_num: usize) -> Result<()> {
+        let node_type = match node_type_from_tag(self.0[NODE_TYPE_OFFSET]) {
+            Some(node_type) => node_type,
+            None => return Err(Error::Corrupt(format!("Page {}: unknown node type {}", page_num, self.0[NODE_TYPE_OFFSET]))),
+        };
+        if self.0[IS_ROOT_OFFSET] > 1 {
+            return Err(Error::Corrupt(format!("Page {}: invalid root flag {}", page_num, self.0[IS_ROOT_OFFSET])));
+        }
+        match node_type {
+            NodeInternal => {
+                let num_keys = self.read_u64(INTERNAL_NODE_NUM_KEYS_OFFSET);
+                if num_keys > (self.layout().internal_node_space_for_cells / (INTERNAL_NODE_SLOT_SIZE + INTERNAL_NODE_KEY_OFFSET)) as u64 {
+                    return Err(Error::Corrupt(format!("Page {}: {} keys do not fit in an internal node", page_num, num_keys)));
+                }
+                let slots_end = Page::internal_node_slot_offset(num_keys as usize);
+                let content_start = self.read_u64(INTERNAL_NODE_CONTENT_START_OFFSET);
+                if content_start < slots_end as u64 || content_start > self.page_size() as u64 {
+                    return Err(Error::Corrupt(format!("Page {}: content start {} outside the cell area", page_num, content_start)));
+                }
+                for cell_num in 0..num_keys as usize {
+                    let offset = self.internal_node_cell_offset(cell_num);
+                    if offset < content_start as usize || offset + INTERNAL_NODE_KEY_OFFSET > self.page_size() {
+                        return Err(Error::Corrupt(format!("Page {}: cell {} at offset {} outside the cell area", page_num, cell_num, offset)));
+                    }
+                    if offset + self.internal_node_cell_size(cell_num) > self.page_size() {
+                        return Err(Error::Corrupt(format!("Page {}: cell {} runs past the end of the page", page_num, cell_num)));
+                    }
+                }
+            }
+            NodeLeaf => {
+                let num_cells = self.read_u64(LEAF_NODE_NUM_CELLS_OFFSET);
+                if num_cells > (self.layout().leaf_node_space_for_cells / LEAF_NODE_SLOT_SIZE) as u64 {
+                    return Err(Error::Corrupt(format!("Page {}: {} cells do not fit in a leaf", page_num, num_cells)));
+                }
+                let slots_end = Page::leaf_node_slot_offset(num_cells as usize);
+                let content_start = self.read_u64(LEAF_NODE_CONTENT_START_OFFSET);
+                if content_start < slots_end as u64 || content_start > self.page_size() as u64 {
+                    return Err(Error::Corrupt(format!("Page {}: content start {} outside the cell area", page_num, content_start)));
+                }
+                let key_header = if self.leaf_node_has_byte_keys() { LEAF_NODE_KEY_LENGTH_SIZE } else { LEAF_NODE_KEY_SIZE };
+                for cell_num in 0..num_cells as usize {
+                    let offset = self.leaf_node_cell_offset(cell_num);
+                    if offset < content_start as usize || offset + key_header > self.page_size() {
+                        return Err(Error::Corrupt(format!("Page {}: cell {} at offset {} outside the cell area", page_num, cell_num, offset)));
+                    }
+                    let key_area = self.leaf_node_key_area(cell_num);
+                    if key_area + LEAF_NODE_RECORD_LENGTH_SIZE + LEAF_NODE_OVERFLOW_PAGE_SIZE > self.layout().leaf_node_max_cell_size {
+                        return Err(Error::Corrupt(format!("Page {}: key of cell {} is too long for a leaf cell", page_num, cell_num)));
+                    }
+                    if offset + key_area + LEAF_NODE_RECORD_LENGTH_SIZE > self.page_size()
+                        || offset + self.leaf_node_cell_size(cell_num) > self.page_size() {
+                        return Err(Error::Corrupt(format!("Page {}: cell {} runs past the end of the page", page_num, cell_num)));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_node_type(&mut self, node_type: NodeType) {
+        self.0[NODE_TYPE_OFFSET] = match node_type {
+            NodeInternal => NODE_TYPE_INTERNAL,
+            NodeLeaf => NODE_TYPE_LEAF,
+        };
+    }
+
+    fn is_node_root(&self) -> bool {
+        self.0[IS_ROOT_OFFSET] != 0
+    }
+
+    fn set_node_root(&mut self, is_root: bool) {
+        self.0[IS_ROOT_OFFSET] = is_root as u8;
+    }
+
+    fn set_internal_node_right_child(&mut self, internal_node_right_child: usize) {
+        self.write_u64(INTERNAL_NODE_RIGHT_CHILD_OFFSET, internal_node_right_child as u64);
+    }
+
+    fn get_internal_node_right_child(&self) -> usize {
+        self.read_u64(INTERNAL_NODE_RIGHT_CHILD_OFFSET) as usize
+    }
+
+    fn set_internal_node_num_keys(&mut self, num_keys: usize) {
+        self.write_u64(INTERNAL_NODE_NUM_KEYS_OFFSET, num_keys as u64);
+    }
+
+    fn get_internal_node_num_keys(&self) -> usize {
+        self.read_u64(INTERNAL_NODE_NUM_KEYS_OFFSET) as usize
+    }
+
+    fn internal_node_content_start(&self) -> usize {
+        self.read_u64(INTERNAL_NODE_CONTENT_START_OFFSET) as usize
+    }
+
+    fn set_internal_node_content_start(&mut self, offset: usize) {
+        self.write_u64(INTERNAL_NODE_CONTENT_START_OFFSET, offset as u64);
+    }
+
+    fn internal_node_slot_offset(cell_num: usize) -> usize {
+        INTERNAL_NODE_HEADER_SIZE + cell_num * INTERNAL_NODE_SLOT_SIZE
+    }
+
+    /// Offset within the page of an internal node cell, read from its slot:
+    /// a child page number followed by the largest key under it.
+    fn internal_node_cell_offset(&self, cell_num: usize) -> usize {
+        self.read_u16(Page::internal_node_slot_offset(cell_num)) as usize
+    }
+
+    fn internal_node_cell_size(&self, cell_num: usize) -> usize {
+        let offset = self.internal_node_cell_offset(cell_num);
+        INTERNAL_NODE_KEY_OFFSET + self.read_u16(offset + INTERNAL_NODE_CHILD_SIZE) as usize
+    }
+
+    fn set_internal_node_cell(&mut self, cell_num: usize, page_num: usize) {
+        self.write_u64(self.internal_node_cell_offset(cell_num), page_num as u64);
+    }
+
+    fn get_internal_node_cell(&self, cell_num: usize) -> usize {
+        self.read_u64(self.internal_node_cell_offset(cell_num)) as usize
+    }
+
+    fn set_internal_node_child(&mut self, child_num: usize, child_page_num: usize) -> Result<()> {
+        let num_keys = self.get_internal_node_num_keys();
+        if child_num > num_keys {
+            return Err(Error::Corrupt(format!("Tried to set child {} of a node with {} keys", child_num, num_keys)));
+        } else if child_num == num_keys {
+            self.set_internal_node_right_child(child_page_num);
+        } else {
+            self.set_internal_node_cell(child_num, child_page_num);
+        }
+        Ok(())
+    }
+
+    fn get_internal_node_child(&self, child_num: usize) -> Result<usize> {
+        let num_keys = self.get_internal_node_num_keys();
+        if child_num > num_keys {
+            return Err(Error::Corrupt(format!("Tried to access child {} of a node with {} keys", child_num, num_keys)));
+        }
+        let child = if child_num == num_keys {
+            self.get_internal_node_right_child()
+        } else {
+            self.get_internal_node_cell(child_num)
+        };
+        if child == INVALID_PAGE_NUM {
+            return Err(Error::Corrupt(format!("Child {} of a node is an invalid page", child_num)));
+        }
+        Ok(child)
+    }
+
+    /// Replace the key of a cell. A longer key moves the cell to the free
+    /// gap, or failing that the node is rewritten without holes.
+    fn set_internal_node_key(&mut self, cell_num: usize, key: &[u8]) {
+        let offset = self.internal_node_cell_offset(cell_num);
+        if INTERNAL_NODE_KEY_OFFSET + key.len() <= self.internal_node_cell_size(cell_num) {
+            self.write_u16(offset + INTERNAL_NODE_CHILD_SIZE, key.len() as u16);
+            self.0[offset + INTERNAL_NODE_KEY_OFFSET..offset + INTERNAL_NODE_KEY_OFFSET + key.len()].copy_from_slice(key);
+            return;
+        }
+        let slots_end = Page::internal_node_slot_offset(self.get_internal_node_num_keys());
+        if self.internal_node_content_start() < slots_end + INTERNAL_NODE_KEY_OFFSET + key.len() {
+            let children = self.internal_node_children();
+            let mut keys = self.internal_node_keys();
+            keys[cell_num] = key.to_vec();
+            self.set_internal_node_cells(&children, &keys);
+            return;
+        }
+        let child_page_num = self.get_internal_node_cell(cell_num);
+        let content_start = self.internal_node_write_cell(child_page_num, key);
+        self.write_u16(Page::internal_node_slot_offset(cell_num), content_start as u16);
+    }
+
+    fn get_internal_node_key(&self, cell_num: usize) -> &[u8] {
+        let offset = self.internal_node_cell_offset(cell_num);
+        &self.0[offset + INTERNAL_NODE_KEY_OFFSET..offset + self.internal_node_cell_size(cell_num)]
+    }
+
+    /// Write a cell at the start of the content area, which must have room
+    /// for it, and return its offset.
+    fn internal_node_write_cell(&mut self, child_page_num: usize, key: &[u8]) -> usize {
+        let content_start = self.internal_node_content_start() - INTERNAL_NODE_KEY_OFFSET - key.len();
+        self.write_u64(content_start, child_page_num as u64);
+        self.write_u16(content_start + INTERNAL_NODE_CHILD_SIZE, key.len() as u16);
+        self.0[content_start + INTERNAL_NODE_KEY_OFFSET..content_start + INTERNAL_NODE_KEY_OFFSET + key.len()].copy_from_slice(key);
+        self.set_internal_node_content_start(content_start);
+        content_start
+    }
+
+    /// Insert a cell (child and key) at `cell_num`, shifting the slots after
+    /// it to the right. The node must have room for the cell and its slot.
+    fn internal_node_insert_cell(&mut self, cell_num: usize, child_page_num: usize, key: &[u8]) {
+        let num_keys = self.get_internal_node_num_keys();
+        let slots_end = Page::internal_node_slot_offset(num_keys + 1);
+        if self.internal_node_content_start() < slots_end + INTERNAL_NODE_KEY_OFFSET + key.len() {
+            let children = self.internal_node_children();
+            let keys = self.internal_node_keys();
+            self.set_internal_node_cells(&children, &keys);
+        }
+        let content_start = self.internal_node_write_cell(child_page_num, key);
+
+        let start = Page::internal_node_slot_offset(cell_num);
+        let end = Page::internal_node_slot_offset(num_keys);
+        self.0.copy_within(start..end, start + INTERNAL_NODE_SLOT_SIZE);
+        self.write_u16(start, content_start as u16);
+        self.set_internal_node_num_keys(num_keys + 1);
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.0[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.0[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.0[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.0[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn write_u64(&mut self, offset: usize, value: u64) {
+        self.0[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn initialize_header(&mut self) {
+        self.0[HEADER_MAGIC_OFFSET..HEADER_MAGIC_OFFSET + HEADER_MAGIC_SIZE].copy_from_slice(HEADER_MAGIC);
+        self.write_u32(HEADER_FORMAT_VERSION_OFFSET, HEADER_FORMAT_VERSION);
+        self.write_u32(HEADER_PAGE_SIZE_OFFSET, self.page_size() as u32);
+        self.set_header_root_page(0);
+        self.set_header_page_count(0);
+        self.set_header_free_list_head(0);
+    }
+
+    fn get_header_magic(&self) -> &[u8] {
+        &self.0[HEADER_MAGIC_OFFSET..HEADER_MAGIC_OFFSET + HEADER_MAGIC_SIZE]
+    }
+
+    fn get_header_format_version(&self) -> u32 {
+        self.read_u32(HEADER_FORMAT_VERSION_OFFSET)
+    }
+
+    fn set_header_format_version(&mut self, version: u32) {
+        self.write_u32(HEADER_FORMAT_VERSION_OFFSET, version);
+    }
+
+    fn get_header_page_size(&self) -> usize {
+        self.read_u32(HEADER_PAGE_SIZE_OFFSET) as usize
+    }
+
+    fn get_header_root_page(&self) -> usize {
+        self.read_u64(HEADER_ROOT_PAGE_OFFSET) as usize
+    }
+
+    fn set_header_root_page(&mut self, root_page_num: usize) {
+        self.write_u64(HEADER_ROOT_PAGE_OFFSET, root_page_num as u64);
+    }
+
+    fn get_header_page_count(&self) -> usize {
+        self.read_u64(HEADER_PAGE_COUNT_OFFSET) as usize
+    }
+
+    fn set_header_page_count(&mut self, page_count: usize) {
+        self.write_u64(HEADER_PAGE_COUNT_OFFSET, page_count as u64);
+    }
+
+    fn get_header_free_list_head(&self) -> usize {
+        self.read_u64(HEADER_FREE_LIST_HEAD_OFFSET) as usize
+    }
+
+    fn set_header_free_list_head(&mut self, trunk_page_num: usize) {
+        self.write_u64(HEADER_FREE_LIST_HEAD_OFFSET, trunk_page_num as u64);
+    }
+
+    fn get_free_trunk_next(&self) -> usize {
+        self.read_u64(FREE_TRUNK_NEXT_OFFSET) as usize
+    }
+
+    fn set_free_trunk_next(&mut self, next_trunk: usize) {
+        self.write_u64(FREE_TRUNK_NEXT_OFFSET, next_trunk as u64);
+    }
+
+    fn get_free_trunk_num_leaves(&self) -> usize {
+        self.read_u64(FREE_TRUNK_NUM_LEAVES_OFFSET) as usize
+    }
+
+    fn set_free_trunk_num_leaves(&mut self, num_leaves: usize) {
+        self.write_u64(FREE_TRUNK_NUM_LEAVES_OFFSET, num_leaves as u64);
+    }
+
+    fn get_free_trunk_leaf(&self, leaf_num: usize) -> usize {
+        self.read_u64(FREE_TRUNK_HEADER_SIZE + leaf_num * FREE_TRUNK_LEAF_SIZE) as usize
+    }
+
+    fn set_free_trunk_leaf(&mut self, leaf_num: usize, page_num: usize) {
+        self.write_u64(FREE_TRUNK_HEADER_SIZE + leaf_num * FREE_TRUNK_LEAF_SIZE, page_num as u64);
+    }
+
+    /// Rewrite a tree node of format version 5 in the current layout. That
+    /// format stored the parent, counts, next leaf and internal cells as raw
+    /// `usize` values, which are read in the width and byte order of this
+    /// machine. Fails if the page is not a node or its counts and cell
+    /// offsets run past the page, or if its cells no longer fit behind the
+    /// wider header.
+    fn migrate_native_node(&mut self, page_num: usize) -> Result<()> {
+        const WIDTH: usize = mem::size_of::<usize>();
+        let old = self.clone();
+        let page_size = old.page_size();
+        let native = |offset: usize| usize::from_ne_bytes(old.0[offset..offset + WIDTH].try_into().unwrap());
+        let is_root = old.is_node_root();
+        let parent_page_num = native(PARENT_POINTER_OFFSET);
+        let node_type = match node_type_from_tag(old.0[NODE_TYPE_OFFSET]) {
+            Some(node_type) => node_type,
+            None => return Err(Error::Corrupt(format!("Page {}: unknown node type {}", page_num, old.0[NODE_TYPE_OFFSET]))),
+        };
+        let does_not_fit = || Error::Format(format!("Page {} does not fit the portable format. Cannot migrate the file", page_num));
+        match node_type {
+            NodeLeaf => {
+                let num_cells = native(PARENT_POINTER_OFFSET + WIDTH);
+                let next_leaf = native(PARENT_POINTER_OFFSET + 2 * WIDTH);
+                let slots = PARENT_POINTER_OFFSET + 3 * WIDTH + LEAF_NODE_CONTENT_START_SIZE;
+                if num_cells > (page_size - slots) / LEAF_NODE_SLOT_SIZE {
+                    return Err(Error::Corrupt(format!("Page {}: {} cells do not fit in a leaf", page_num, num_cells)));
+                }
+                let slots_end = slots + num_cells * LEAF_NODE_SLOT_SIZE;
+                let mut cells = Vec::with_capacity(num_cells);
+                for cell_num in 0..num_cells {
+                    let offset = old.read_u16(slots + cell_num * LEAF_NODE_SLOT_SIZE) as usize;
+                    if offset < slots_end || offset + LEAF_NODE_KEY_SIZE + LEAF_NODE_RECORD_LENGTH_SIZE > page_size {
+                        return Err(Error::Corrupt(format!("Page {}: cell {} at offset {} outside the cell area", page_num, cell_num, offset)));
+                    }
+                    let length = old.read_u32(offset + LEAF_NODE_KEY_SIZE) as usize;
+                    let cell_size = old.layout().leaf_cell_size(LEAF_NODE_KEY_SIZE, length);
+                    if offset + cell_size > page_size {
+                        return Err(Error::Corrupt(format!("Page {}: cell {} runs past the end of the page", page_num, cell_num)));
+                    }
+                    cells.push(old.0[offset..offset + cell_size].to_vec());
+                }
+                if cells.iter().map(|cell| cell.len() + LEAF_NODE_SLOT_SIZE).sum::<usize>() > self.layout().leaf_node_space_for_cells {
+                    return Err(does_not_fit());
+                }
+                self.initialize_leaf_node(key::INTEGER_KEYS);
+                self.set_leaf_node_next_leaf(next_leaf);
+                self.set_leaf_node_cells(&cells);
+            }
+            NodeInternal => {
+                let num_keys = native(PARENT_POINTER_OFFSET + WIDTH);
+                let cells = PARENT_POINTER_OFFSET + 3 * WIDTH;
+                if num_keys > (page_size - cells) / (2 * WIDTH) {
+                    return Err(Error::Corrupt(format!("Page {}: {} keys do not fit in an internal node", page_num, num_keys)));
+                }
+                let mut children: Vec<usize> = (0..num_keys).map(|i| native(cells + 2 * i * WIDTH)).collect();
+                children.push(native(PARENT_POINTER_OFFSET + 2 * WIDTH));
+                let keys: Vec<Vec<u8>> = (0..num_keys).map(|i| key::integer_key(native(cells + (2 * i + 1) * WIDTH))).collect();
+                if internal_cells_size(&keys) > self.layout().internal_node_space_for_cells {
+                    return Err(does_not_fit());
+                }
+                self.initialize_internal_node();
+                self.set_internal_node_cells(&children, &keys);
+            }
+        }
+        self.set_node_root(is_root);
+        self.set_node_parent(parent_page_num);
+        Ok(())
+    }
+
+    /// The next trunk and the leaf pages of a free-list trunk page of format
+    /// version 5, read like `migrate_native_node` reads nodes.
+    fn native_free_trunk(&self, page_num: usize) -> Result<(usize, Vec<usize>)> {
+        const WIDTH: usize = mem::size_of::<usize>();
+        let native = |offset: usize| usize::from_ne_bytes(self.0[offset..offset + WIDTH].try_into().unwrap());
+        let num_leaves = native(WIDTH);
+        if num_leaves > (self.page_size() - 2 * WIDTH) / WIDTH {
+            return Err(Error::Corrupt(format!("Page {}: {} free pages do not fit in a free-list trunk", page_num, num_leaves)));
+        }
+        Ok((native(0), (0..num_leaves).map(|i| native((2 + i) * WIDTH)).collect()))
+    }
+
+    /// Rewrite an internal node of format version 6 in the slotted layout.
+    /// That format had no content start in the header and stored each cell
+    /// as a child page number and an 8-byte key, at a fixed offset. Returns
+    /// false if its cells would not fit a full node of the current layout.
+    fn migrate_integer_keys_internal_node(&mut self) -> bool {
+        const CELL_SIZE: usize = INTERNAL_NODE_CHILD_SIZE + key::INTEGER_KEY_SIZE;
+        let cells = INTERNAL_NODE_CONTENT_START_OFFSET;
+        let num_keys = self.get_internal_node_num_keys();
+        if cells + num_keys * CELL_SIZE > self.page_size()
+            || num_keys * (INTERNAL_NODE_SLOT_SIZE + INTERNAL_NODE_KEY_OFFSET + key::INTEGER_KEY_SIZE) > self.layout().internal_node_space_for_cells {
+            return false;
+        }
+        let mut children: Vec<usize> = (0..num_keys).map(|i| self.read_u64(cells + i * CELL_SIZE) as usize).collect();
+        children.push(self.get_internal_node_right_child());
+        let keys: Vec<Vec<u8>> = (0..num_keys).map(|i| {
+            let offset = cells + i * CELL_SIZE + INTERNAL_NODE_CHILD_SIZE;
+            self.0[offset..offset + key::INTEGER_KEY_SIZE].to_vec()
+        }).collect();
+        self.set_internal_node_cells(&children, &keys);
+        true
+    }
+
+    /// Return the position of `child_page_num` among the children of this node,
+    /// where `num_keys` stands for the right child.
+    fn internal_node_child_index(&self, child_page_num: usize) -> usize {
+        let num_keys = self.get_internal_node_num_keys();
+        (0..num_keys)
+            .find(|&i| self.get_internal_node_cell(i) == child_page_num)
+            .unwrap_or(num_keys)
+    }
+
+    /// Bytes taken by the cells and slots of this internal node, not
+    /// counting holes.
+    fn internal_node_used_space(&self) -> usize {
+        (0..self.get_internal_node_num_keys())
+            .map(|i| self.internal_node_cell_size(i) + INTERNAL_NODE_SLOT_SIZE)
+            .sum()
+    }
+
+    /// All children of this node in key order, ending with the right child.
+    fn internal_node_children(&self) -> Vec<usize> {
+        let num_keys = self.get_internal_node_num_keys();
+        let mut children: Vec<usize> = (0..num_keys).map(|i| self.get_internal_node_cell(i)).collect();
+        children.push(self.get_internal_node_right_child());
+        children
+    }
+
+    fn internal_node_keys(&self) -> Vec<Vec<u8>> {
+        (0..self.get_internal_node_num_keys()).map(|i| self.get_internal_node_key(i).to_vec()).collect()
+    }
+
+    /// Replace every cell of this internal node, packing them against the end
+    /// of the page. `children` holds one more entry than `keys`; the last
+    /// child becomes the right child.
+    fn set_internal_node_cells(&mut self, children: &[usize], keys: &[Vec<u8>]) {
+        self.set_internal_node_content_start(self.page_size());
+        for (i, key) in keys.iter().enumerate() {
+            let content_start = self.internal_node_write_cell(children[i], key);
+            self.write_u16(Page::internal_node_slot_offset(i), content_start as u16);
+        }
+        self.set_internal_node_num_keys(keys.len());
+        self.set_internal_node_right_child(children[keys.len()]);
+    }
+
+    /// Remove a cell (child and key), shifting the slots after it to the
+    /// left. The cell's bytes are left as a hole like a leaf's.
+    fn internal_node_remove_cell(&mut self, cell_num: usize) {
+        let num_keys = self.get_internal_node_num_keys();
+        let offset = self.internal_node_cell_offset(cell_num);
+        if offset == self.internal_node_content_start() {
+            self.set_internal_node_content_start(offset + self.internal_node_cell_size(cell_num));
+        }
+        let start = Page::internal_node_slot_offset(cell_num + 1);
+        let end = Page::internal_node_slot_offset(num_keys);
+        self.0.copy_within(start..end, start - INTERNAL_NODE_SLOT_SIZE);
+        self.set_internal_node_num_keys(num_keys - 1);
+    }
+}
+
+const PAGER_DEFAULT_MAX_FRAMES: usize = 100;
+const PAGER_MIN_FRAMES: usize = 4;
+const WAL_AUTOCHECKPOINT_FRAMES: usize = 1000;
+
+/// One slot of the buffer pool.
+struct Frame {
+    page_num: usize,
+    page: Page,
+    dirty: bool,
+    last_used: u64,
+    /// Whether the page has passed `check_node` since it was read.
+    checked: bool,
+}
+
+/// Page cache over the database file.
+///
+/// At most `max_frames` pages are held in memory. On a miss with every frame
+/// taken, the least recently used frame is evicted, and spilled to the
+/// write-ahead log first if it is dirty, so the file itself can grow without
+/// limit. The database file is only written by checkpoints.
+struct Pager {
+    file_descriptor: File,
+    file_length : usize,
+    num_pages : usize,
+    max_frames: usize,
+    frames: Vec<Frame>,
+    page_table: HashMap<usize, usize>,
+    clock: u64,
+    wal: Wal,
+    /// Sizes that follow from the database's page size.
+    layout: Layout,
+}
+
+impl Pager {
+    /// Open the database file, creating it with pages of `page_size` bytes
+    /// if it is empty. An existing database keeps the page size recorded in
+    /// its header or log.
+    fn pager_open(filename : &str, max_frames: usize, page_size: usize) -> Result<Pager> {
+        let path = Path::new(filename);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .read(true)
+            .open(path)?;
+
+        /* Refuse foreign files before a log is created next to them */
+        let file_lenth = file.metadata()?.len() as usize;
+        let mut page_size = page_size;
+        if file_lenth > 0 {
+            let mut header = Page::new(MIN_PAGE_SIZE);
+            let _ = file.read(&mut header.0)?;
+            if !Wal::wal_exists(filename) {
+                Pager::validate_header_format(&header)?;
+            }
+            if valid_page_size(header.get_header_page_size()) {
+                page_size = header.get_header_page_size();
+            }
+        }
+        /* Pages may be in the log before any reached the file */
+        if let Some(log_page_size) = Wal::wal_page_size(filename) {
+            page_size = log_page_size;
+        }
+
+        let max_frames = max_frames.max(PAGER_MIN_FRAMES);
+        let mut pager = Pager {
+            file_descriptor: file,
+            file_length: file_lenth,
+            num_pages: 0,
+            max_frames,
+            frames: Vec::with_capacity(max_frames),
+            page_table: HashMap::with_capacity(max_frames),
+            clock: 0,
+            wal: Wal::wal_open(filename, page_size)?,
+            layout: Layout::new(page_size),
+        };
+
+        /* Replay transactions that committed before the last shutdown */
+        if pager.wal.num_committed_frames() > 0 {
+            pager.checkpoint()?;
+        }
+
+        if !pager.file_length.is_multiple_of(page_size) {
+            return Err(Error::Corrupt("Db file is not a whole number of pages".to_string()));
+        }
+        pager.num_pages = pager.file_length / page_size;
+
+        if pager.num_pages == 0 {
+            let root_page_num = HEADER_PAGE_NUM + 1;
+            let header = pager.get_page(HEADER_PAGE_NUM)?;
+            header.initialize_header();
+            header.set_header_root_page(root_page_num);
+            let root_node = pager.get_page(root_page_num)?;
+            root_node.initialize_leaf_node(key::INTEGER_KEYS);
+            root_node.set_node_root(true);
+            pager.commit()?;
+        } else {
+            pager.validate_header()?;
+        }
+        Ok(pager)
+    }
+
+    /// Check that a header page, or the start of one, belongs to a database
+    /// this version can read.
+    fn validate_header_format(header: &Page) -> Result<()> {
+        if header.get_header_magic() != HEADER_MAGIC {
+            return Err(Error::Format("File is not a db_simple database".to_string()));
+        }
+        let version = header.get_header_format_version();
+        if version != HEADER_FORMAT_VERSION && version != NATIVE_FORMAT_VERSION && version != INTEGER_KEYS_FORMAT_VERSION {
+            return Err(Error::Format(format!("Unsupported database format version {} (expected {})",
+                                             header.get_header_format_version(), HEADER_FORMAT_VERSION)));
+        }
+        if !valid_page_size(header.get_header_page_size()) {
+            return Err(Error::Format(format!("Unsupported database page size {}", header.get_header_page_size())));
+        }
+        Ok(())
+    }
+
+    /// Check that page 0 is a header this version can read, and pick up the
+    /// page count recorded in it.
+    fn validate_header(&mut self) -> Result<()> {
+        let header = self.read_page(HEADER_PAGE_NUM)?;
+        Pager::validate_header_format(&header)?;
+        if header.get_header_page_size() != self.layout.page_size {
+            return Err(Error::Format(format!("Database page size {} does not match its log's {}",
+                                             header.get_header_page_size(), self.layout.page_size)));
+        }
+
+        let page_count = header.get_header_page_count();
+        if page_count > self.num_pages {
+            return Err(Error::Corrupt(format!("Db file holds {} pages but its header records {}", self.num_pages, page_count)));
+        }
+        let root_page_num = header.get_header_root_page();
+        if root_page_num == HEADER_PAGE_NUM || root_page_num >= page_count {
+            return Err(Error::Corrupt(format!("Root page {} is out of range", root_page_num)));
+        }
+        self.num_pages = page_count;
+        Ok(())
+    }
+
+    fn get_format_version(&mut self) -> Result<u32> {
+        Ok(self.get_page_view(HEADER_PAGE_NUM)?.get_header_format_version())
+    }
+
+    /// Root page of the catalog, the tree listing every table.
+    fn get_catalog_root_page_num(&mut self) -> Result<usize> {
+        Ok(self.get_page_view(HEADER_PAGE_NUM)?.get_header_root_page())
+    }
+
+    /// Fetch a page for modification. The page is marked dirty and will be
+    /// logged on eviction or commit.
+    fn get_page(&mut self, page_num : usize) -> Result<&mut Page> {
+        let frame_idx = self.fetch_frame(page_num)?;
+        let frame = &mut self.frames[frame_idx];
+        frame.dirty = true;
+        frame.checked = false;
+        Ok(&mut frame.page)
+    }
+
+    /// Fetch a page for reading only; the page is not marked dirty.
+    fn get_page_view(&mut self, page_num: usize) -> Result<&Page> {
+        let frame_idx = self.fetch_frame(page_num)?;
+        Ok(&self.frames[frame_idx].page)
+    }
+
+    /// Fetch a tree node for writing. A node read from storage is checked
+    /// with `check_node` on first use, so that a corrupt page is reported
+    /// instead of being trusted by the node accessors.
+    fn get_node(&mut self, page_num: usize) -> Result<&mut Page> {
+        let frame_idx = self.fetch_checked_frame(page_num)?;
+        let frame = &mut self.frames[frame_idx];
+        frame.dirty = true;
+        Ok(&mut frame.page)
+    }
+
+    /// Fetch a tree node for reading only, checked like `get_node`.
+    fn get_node_view(&mut self, page_num: usize) -> Result<&Page> {
+        let frame_idx = self.fetch_checked_frame(page_num)?;
+        Ok(&self.frames[frame_idx].page)
+    }
+
+    fn fetch_checked_frame(&mut self, page_num: usize) -> Result<usize> {
+        let frame_idx = self.fetch_frame(page_num)?;
+        let frame = &mut self.frames[frame_idx];
+        if !frame.checked {
+            frame.page.check_node(page_num)?;
+            frame.checked = true;
+        }
+        Ok(frame_idx)
+    }
+
+    /// Return the index of the frame holding `page_num`, loading the page
+    /// into a free or evicted frame on a miss.
+    fn fetch_frame(&mut self, page_num: usize) -> Result<usize> {
+        self.clock += 1;
+        if let Some(&frame_idx) = self.page_table.get(&page_num) {
+            self.frames[frame_idx].last_used = self.clock;
+            return Ok(frame_idx);
+        }
+
+        /* New pages are allocated at the end, so only a corrupt page number lies past it */
+        if page_num > self.num_pages {
+            return Err(Error::Corrupt(format!("Page {} is past the end of the file", page_num)));
+        }
+        let frame = Frame {
+            page_num,
+            page: self.read_page(page_num)?,
+            dirty: false,
+            last_used: self.clock,
+            checked: false,
+        };
+        let frame_idx = if self.frames.len() < self.max_frames {
+            self.frames.push(frame);
+            self.frames.len() - 1
+        } else {
+            let victim = self.lru_frame();
+            self.evict_frame(victim)?;
+            self.frames[victim] = frame;
+            victim
+        };
+        self.page_table.insert(page_num, frame_idx);
+
+        if page_num >= self.num_pages {
+            self.num_pages = page_num + 1;
+        }
+        Ok(frame_idx)
+    }
+
+    fn lru_frame(&self) -> usize {
+        let mut victim = 0;
+        for (frame_idx, frame) in self.frames.iter().enumerate() {
+            if frame.last_used < self.frames[victim].last_used {
+                victim = frame_idx;
+            }
+        }
+        victim
+    }
+
+    /// Drop a frame from the pool. A dirty page is spilled to the log as part
+    /// of the open transaction, since the database file may only change at a
+    /// checkpoint.
+    fn evict_frame(&mut self, frame_idx: usize) -> Result<()> {
+        let frame = &mut self.frames[frame_idx];
+        if frame.dirty {
+            self.wal.append_frame(frame.page_num, &frame.page, 0)?;
+            frame.dirty = false;
+        }
+        self.page_table.remove(&frame.page_num);
+        Ok(())
+    }
+
+    /// Read a page from the log, or from the database file if the log holds no
+    /// newer image. Pages past the end of the file come back zeroed.
+    fn read_page(&mut self, page_num: usize) -> Result<Page> {
+        if let Some(page) = self.wal.read_page(page_num)? {
+            return Ok(page);
+        }
+
+        let mut page = Page::new(self.layout.page_size);
+        // partial page at the end of the file
+        let num_pages = self.file_length.div_ceil(self.layout.page_size);
+
+        if page_num < num_pages {
+            self.file_descriptor.seek(SeekFrom::Start((page_num * self.layout.page_size) as u64))?;
+            let _ = self.file_descriptor.read(&mut page.0)?;
+        }
+        Ok(page)
+    }
+
+    /// A cell holding `record` under `key`, for a leaf of keys of the given
+    /// format. Past `leaf_max_local` bytes the record is written to a chain of
+    /// overflow pages.
+    fn leaf_cell(&mut self, key: &[u8], record: &[u8], format: KeyFormat) -> Result<Vec<u8>> {
+        let key_area = if format.fixed_size { LEAF_NODE_KEY_SIZE } else { LEAF_NODE_KEY_LENGTH_SIZE + key.len() };
+        let local = record.len().min(self.layout.leaf_max_local(key_area));
+        let mut cell = Vec::with_capacity(self.layout.leaf_cell_size(key_area, record.len()));
+        if !format.fixed_size {
+            cell.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        }
+        cell.extend_from_slice(key);
+        cell.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        cell.extend_from_slice(&record[..local]);
+        if local < record.len() {
+            /* Write the chain back to front so that each page can name the next */
+            let mut next_page_num = 0;
+            for chunk in record[local..].chunks(self.layout.overflow_data_size).rev() {
+                let page_num = self.get_unused_page_num()?;
+                let page = self.get_page(page_num)?;
+                page.write_u64(OVERFLOW_NEXT_OFFSET, next_page_num as u64);
+                page.0[OVERFLOW_DATA_OFFSET..OVERFLOW_DATA_OFFSET + chunk.len()].copy_from_slice(chunk);
+                next_page_num = page_num;
+            }
+            cell.extend_from_slice(&(next_page_num as u64).to_le_bytes());
+        }
+        Ok(cell)
+    }
+
+    /// The key and record of a leaf cell, the record put back together from
+    /// the cell and any overflow pages.
+    fn leaf_node_record(&mut self, page_num: usize, cell_num: usize) -> Result<(Vec<u8>, Vec<u8>)> {
+        let page = self.get_node_view(page_num)?;
+        let key = page.leaf_node_key(cell_num).to_vec();
+        let length = page.leaf_node_record_length(cell_num);
+        let mut record = page.leaf_node_local_record(cell_num).to_vec();
+        let mut overflow_page_num = page.leaf_node_overflow_page(cell_num);
+        while record.len() < length {
+            if overflow_page_num == 0 || overflow_page_num >= self.num_pages {
+                return Err(Error::Corrupt(format!("Page {}: overflow page {} out of range", page_num, overflow_page_num)));
+            }
+            let chunk = (length - record.len()).min(self.layout.overflow_data_size);
+            let page = self.get_page_view(overflow_page_num)?;
+            record.extend_from_slice(&page.0[OVERFLOW_DATA_OFFSET..OVERFLOW_DATA_OFFSET + chunk]);
+            overflow_page_num = page.read_u64(OVERFLOW_NEXT_OFFSET) as usize;
+        }
+        Ok((key, record))
+    }
+
+    /// The row stored in a leaf cell.
+    fn leaf_node_row(&mut self, schema: &Schema, page_num: usize, cell_num: usize) -> Result<Row> {
+        let (key, record) = self.leaf_node_record(page_num, cell_num)?;
+        let key = key::integer_key_value(&key);
+        match schema.decode_record(key, &record) {
+            Some(values) => Ok(Row { key, values }),
+            None => Err(Error::Corrupt(format!("Page {}: malformed record for key {}", page_num, key))),
+        }
+    }
+
+    /// Return the overflow pages of a leaf cell to the free list. The whole
+    /// chain is checked first, so that a corrupt one frees nothing.
+    fn free_overflow(&mut self, page_num: usize, cell_num: usize) -> Result<()> {
+        let page = self.get_page_view(page_num)?;
+        let overflow_length = page.leaf_node_record_length(cell_num) - page.leaf_node_local_record(cell_num).len();
+        let mut overflow_page_num = page.leaf_node_overflow_page(cell_num);
+        let mut chain = Vec::new();
+        for _ in 0..overflow_length.div_ceil(self.layout.overflow_data_size) {
+            if overflow_page_num == 0 || overflow_page_num >= self.num_pages {
+                return Err(Error::Corrupt(format!("Page {}: overflow page {} out of range", page_num, overflow_page_num)));
+            }
+            chain.push(overflow_page_num);
+            overflow_page_num = self.get_page_view(overflow_page_num)?.read_u64(OVERFLOW_NEXT_OFFSET) as usize;
+        }
+        let mut sorted = chain.clone();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(Error::Corrupt(format!("Page {}: overflow chain loops back on itself", page_num)));
+        }
+        for overflow_page_num in chain {
+            self.free_page(overflow_page_num)?;
+        }
+        Ok(())
+    }
+
+    fn get_node_max_key(&mut self, mut page_num: usize) -> Result<Vec<u8>> {
+        let mut depth = 0;
+        loop {
+            check_tree_depth(page_num, depth)?;
+            let page = self.get_node_view(page_num)?;
+            match page.get_node_type() {
+                NodeInternal => {
+                    page_num = page.get_internal_node_child(page.get_internal_node_num_keys())?;
+                },
+                NodeLeaf => {
+                    let num_cells = page.leaf_node_num_cells();
+                    if num_cells == 0 {
+                        return Err(Error::Corrupt(format!("Page {}: empty leaf has no max key", page_num)));
+                    }
+                    return Ok(page.leaf_node_key(num_cells - 1).to_vec());
+                }
+            }
+            depth += 1;
+        }
+    }
+
+    /// Fail if a walk along a leaf chain reached `page_num` after `num_leaves`
+    /// leaves, more than the file has pages: the chain loops back on itself.
+    fn check_leaf_chain(&self, page_num: usize, num_leaves: usize) -> Result<()> {
+        if num_leaves > self.num_pages {
+            return Err(Error::Corrupt(format!("Page {}: leaf chain longer than the {} pages of the file", page_num, self.num_pages)));
+        }
+        Ok(())
+    }
+
+    /// Make every change since the last commit durable: log the dirty pages,
+    /// then the header page as the commit frame, and sync the log.
+    fn commit(&mut self) -> Result<()> {
+        if !self.frames.iter().any(|frame| frame.dirty) && !self.wal.has_pending_frames() {
+            return Ok(());
+        }
+        let num_pages = self.num_pages;
+        self.get_page(HEADER_PAGE_NUM)?.set_header_page_count(num_pages);
+
+        let mut dirty: Vec<usize> = (0..self.frames.len())
+            .filter(|&frame_idx| self.frames[frame_idx].dirty && self.frames[frame_idx].page_num != HEADER_PAGE_NUM)
+            .collect();
+        dirty.sort_by_key(|&frame_idx| self.frames[frame_idx].page_num);
+        for frame_idx in dirty {
+            let frame = &mut self.frames[frame_idx];
+            self.wal.append_frame(frame.page_num, &frame.page, 0)?;
+            frame.dirty = false;
+        }
+
+        let frame_idx = self.fetch_frame(HEADER_PAGE_NUM)?;
+        let frame = &mut self.frames[frame_idx];
+        self.wal.append_frame(HEADER_PAGE_NUM, &frame.page, num_pages)?;
+        frame.dirty = false;
+        self.wal.commit()?;
+
+        if self.wal.num_committed_frames() >= WAL_AUTOCHECKPOINT_FRAMES {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Copy the newest committed image of every logged page into the database
+    /// file, sync it, and start the log over. Must not run while a
+    /// transaction has pages in the log.
+    fn checkpoint(&mut self) -> Result<()> {
+        for page_num in self.wal.committed_pages() {
+            if let Some(page) = self.wal.read_page(page_num)? {
+                self.write_page(page_num, &page)?;
+            }
+        }
+        self.file_descriptor.sync_all()?;
+        self.wal.reset()
+    }
+
+    /// Undo every change since the last commit. Dirty frames, and clean frames
+    /// read back from pages the transaction spilled, are dropped from the pool
+    /// so the next access reads the committed image.
+    fn rollback(&mut self) -> Result<()> {
+        let wal = &self.wal;
+        self.frames.retain(|frame| !frame.dirty && !wal.is_pending(frame.page_num));
+        self.wal.rollback()?;
+
+        self.page_table.clear();
+        for (frame_idx, frame) in self.frames.iter().enumerate() {
+            self.page_table.insert(frame.page_num, frame_idx);
+        }
+        self.num_pages = self.get_page_view(HEADER_PAGE_NUM)?.get_header_page_count();
+        Ok(())
+    }
+
+    /// Commit outstanding changes, checkpoint them and remove the log.
+    fn pager_close(&mut self) -> Result<()> {
+        self.commit()?;
+        self.checkpoint()?;
+        self.wal.wal_close()?;
+        Ok(())
+    }
+
+    fn write_page(&mut self, page_num: usize, page: &Page) -> Result<()> {
+        self.file_descriptor.seek(SeekFrom::Start((page_num * self.layout.page_size) as u64))?;
+        self.file_descriptor.write_all(&page.0)?;
+        self.file_length = self.file_length.max((page_num + 1) * self.layout.page_size);
+        Ok(())
+    }
+
+    /// Hand out a page for a new node, reusing a free page when there is one.
+    fn get_unused_page_num(&mut self) -> Result<usize> {
+        let head = self.get_free_list_head()?;
+        if head == 0 {
+            return Ok(self.num_pages);
+        }
+
+        self.check_free_page_num(head)?;
+        let num_leaves = self.get_free_trunk_num_leaves(head)?;
+        if num_leaves > 0 {
+            let page_num = self.get_page_view(head)?.get_free_trunk_leaf(num_leaves - 1);
+            self.check_free_page_num(page_num)?;
+            self.get_page(head)?.set_free_trunk_num_leaves(num_leaves - 1);
+            return Ok(page_num);
+        }
+        /* The trunk holds no more free pages, so hand out the trunk page itself */
+        let next_trunk = self.get_page_view(head)?.get_free_trunk_next();
+        if next_trunk != 0 {
+            self.check_free_page_num(next_trunk)?;
+        }
+        self.set_free_list_head(next_trunk)?;
+        Ok(head)
+    }
+
+    /// Fail unless a page number read from the free list names a page of
+    /// the file other than the header.
+    fn check_free_page_num(&self, page_num: usize) -> Result<()> {
+        if page_num == HEADER_PAGE_NUM || page_num >= self.num_pages {
+            return Err(Error::Corrupt(format!("Free page {} is out of range", page_num)));
+        }
+        Ok(())
+    }
+
+    /// Number of free pages held by a free-list trunk, checked against the
+    /// room the trunk has for them.
+    fn get_free_trunk_num_leaves(&mut self, trunk_page_num: usize) -> Result<usize> {
+        let num_leaves = self.get_page_view(trunk_page_num)?.get_free_trunk_num_leaves();
+        if num_leaves > self.layout.free_trunk_max_leaves {
+            return Err(Error::Corrupt(format!("Page {}: {} free pages do not fit in a free-list trunk", trunk_page_num, num_leaves)));
+        }
+        Ok(num_leaves)
+    }
+
+    /// Put a page that is no longer part of any tree on the free list.
+    ///
+    /// The free list is a chain of trunk pages, each holding the numbers of up
+    /// to `free_trunk_max_leaves` other free pages.
+    fn free_page(&mut self, page_num: usize) -> Result<()> {
+        let head = self.get_free_list_head()?;
+        if head != 0 {
+            self.check_free_page_num(head)?;
+            let num_leaves = self.get_free_trunk_num_leaves(head)?;
+            if num_leaves < self.layout.free_trunk_max_leaves {
+                let trunk = self.get_page(head)?;
+                trunk.set_free_trunk_leaf(num_leaves, page_num);
+                trunk.set_free_trunk_num_leaves(num_leaves + 1);
+                return Ok(());
+            }
+        }
+
+        let trunk = self.get_page(page_num)?;
+        *trunk = Page::new(trunk.page_size());
+        trunk.set_free_trunk_next(head);
+        trunk.set_free_trunk_num_leaves(0);
+        self.set_free_list_head(page_num)?;
+        Ok(())
+    }
+
+    /// First trunk page of the free list, or 0 when the list is empty.
+    fn get_free_list_head(&mut self) -> Result<usize> {
+        Ok(self.get_page_view(HEADER_PAGE_NUM)?.get_header_free_list_head())
+    }
+
+    fn set_free_list_head(&mut self, trunk_page_num: usize) -> Result<()> {
+        self.get_page(HEADER_PAGE_NUM)?.set_header_free_list_head(trunk_page_num);
+        Ok(())
+    }
+
+    fn num_free_pages(&mut self) -> Result<usize> {
+        let mut num_free_pages = 0;
+        let mut trunk_page_num = self.get_free_list_head()?;
+        while trunk_page_num != 0 {
+            self.check_free_page_num(trunk_page_num)?;
+            num_free_pages += 1 + self.get_free_trunk_num_leaves(trunk_page_num)?;
+            /* A free list that loops back on itself would hold more pages than the file */
+            if num_free_pages >= self.num_pages {
+                return Err(Error::Corrupt(format!("Free list holds more than the {} pages of the file", self.num_pages)));
+            }
+            trunk_page_num = self.get_page_view(trunk_page_num)?.get_free_trunk_next();
+        }
+        Ok(num_free_pages)
+    }
+
+    fn get_leftmost_leaf_page_num(&mut self, mut page_num: usize) -> Result<usize> {
+        let mut depth = 0;
+        loop {
+            check_tree_depth(page_num, depth)?;
+            let page = self.get_node_view(page_num)?;
+
+            if page.is_leaf_node() {
+                return Ok(page_num);
+            }
+            page_num = page.get_internal_node_child(0)?;
+            depth += 1;
+        }
+    }
+
+    fn get_rightmost_leaf_page_num(&mut self, mut page_num: usize) -> Result<usize> {
+        let mut depth = 0;
+        loop {
+            check_tree_depth(page_num, depth)?;
+            let page = self.get_node_view(page_num)?;
+
+            if page.is_leaf_node() {
+                return Ok(page_num);
+            }
+            page_num = page.get_internal_node_child(page.get_internal_node_num_keys())?;
+            depth += 1;
+        }
+    }
+}
+
+/// A non-NULL value found more than once among `values`, each paired with
+/// where it comes from, such as a primary key, and where its second
+/// occurrence comes from.
+fn repeated_value(mut values: Vec<(&Literal, usize)>) -> Option<(&Literal, usize)> {
+    values.retain(|(value, _)| **value != Literal::Null);
+    /* The sort is stable, so equal values keep their order */
+    values.sort_by(|a, b| planner::compare_literals(a.0, b.0));
+    values.windows(2).find(|pair| planner::compare_literals(pair[0].0, pair[1].0) == Ordering::Equal).map(|pair| pair[1])
+}
+
+/// An error met on a line of an imported file, naming the line. Errors of
+/// the file or the database itself are passed on as they are.
+fn line_error(line: usize, error: Error) -> Error {
+    let message = match error {
+        Error::Invalid(message) | Error::Constraint(message) => message,
+        error if error.is_fatal() => return error,
+        error => error.to_string().trim_end_matches('.').to_string(),
+    };
+    Error::Invalid(format!("Line {}: {}", line, message))
+}
+
+/// An open database: the pager and the tables listed in its catalog.
+struct Table {
+    pager: Pager,
+    /// The catalog, whose rows describe the other tables.
+    catalog: Schema,
+    /// Tables in the catalog, in the order they were created.
+    schemas: Vec<Schema>,
+    /// Set between `begin` and `commit`/`rollback`; statements no longer
+    /// commit on their own.
+    in_transaction: bool,
+}
+
+impl Table {
+    fn db_open(filename : &str, max_frames: usize, page_size: usize) -> Result<Self> {
+        let mut pager = Pager::pager_open(filename, max_frames, page_size)?;
+        let catalog_root_page_num = pager.get_catalog_root_page_num()?;
+
+        let mut table = Self {
+            pager,
+            catalog: Table::parse_schema(catalog::CATALOG_SQL, catalog_root_page_num)?,
+            schemas: Vec::new(),
+            in_transaction: false,
+        };
+        match table.pager.get_format_version()? {
+            NATIVE_FORMAT_VERSION => table.migrate_native_format()?,
+            INTEGER_KEYS_FORMAT_VERSION => table.migrate_integer_keys_format()?,
+            _ => {}
+        }
+        table.load_catalog()?;
+
+        /* A new database starts out with the table the shorthand statements work on */
+        if table.schemas.is_empty() {
+            let users = Table::parse_schema(catalog::USERS_SQL, 0)?;
+            table.create_table(users)?;
+            table.pager.commit()?;
+        }
+        Ok(table)
+    }
+
+    /// Rewrite a database of format version 5 in the current format, as one
+    /// transaction. Overflow pages were already portable. The catalog tree
+    /// is migrated first so that it can be read for the roots of the others,
+    /// and the free list is rebuilt from the pages it held. The result is
+    /// checkpointed so the file itself no longer reads as version 5.
+    fn migrate_native_format(&mut self) -> Result<()> {
+        let mut free_pages = Vec::new();
+        let mut trunk_page_num = self.pager.get_free_list_head()?;
+        while trunk_page_num != 0 {
+            let num_pages = self.pager.num_pages;
+            if trunk_page_num >= num_pages {
+                return Err(Error::Corrupt(format!("Free page {} is out of range", trunk_page_num)));
+            }
+            let (next_trunk_page_num, leaves) = self.pager.get_page_view(trunk_page_num)?.native_free_trunk(trunk_page_num)?;
+            if let Some(page_num) = leaves.iter().find(|&&page_num| page_num == HEADER_PAGE_NUM || page_num >= num_pages) {
+                return Err(Error::Corrupt(format!("Free page {} is out of range", page_num)));
+            }
+            free_pages.push(trunk_page_num);
+            free_pages.extend(leaves);
+            /* A free list that loops back on itself would hold more pages than the file */
+            if free_pages.len() >= num_pages {
+                return Err(Error::Corrupt(format!("Free list holds more than the {} pages of the file", num_pages)));
+            }
+            trunk_page_num = next_trunk_page_num;
+        }
+        self.pager.set_free_list_head(0)?;
+
+        self.migrate_native_tree(self.catalog.root_page_num, 0)?;
+        self.load_catalog()?;
+        for root_page_num in self.schemas.iter().map(|schema| schema.root_page_num).collect::<Vec<_>>() {
+            self.migrate_native_tree(root_page_num, 0)?;
+        }
+        for page_num in free_pages {
+            self.pager.free_page(page_num)?;
+        }
+        self.pager.get_page(HEADER_PAGE_NUM)?.set_header_format_version(HEADER_FORMAT_VERSION);
+        self.pager.commit()?;
+        self.pager.checkpoint()
+    }
+
+    fn migrate_native_tree(&mut self, page_num: usize, depth: usize) -> Result<()> {
+        check_tree_depth(page_num, depth)?;
+        let page = self.pager.get_page(page_num)?;
+        page.migrate_native_node(page_num)?;
+        if !page.is_leaf_node() {
+            for child_page_num in page.internal_node_children() {
+                self.migrate_native_tree(child_page_num, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite a database of format version 6 in the current format, as one
+    /// transaction. The internal nodes of every tree take the slotted layout,
+    /// leaves being unchanged, and each index, whose tree was keyed by a hash
+    /// of the indexed value, is emptied and refilled from its table. The
+    /// result is checkpointed like a migration from version 5.
+    fn migrate_integer_keys_format(&mut self) -> Result<()> {
+        self.migrate_integer_keys_tree(self.catalog.root_page_num, 0)?;
+        self.load_catalog()?;
+        for schema in self.schemas.clone() {
+            self.migrate_integer_keys_tree(schema.root_page_num, 0)?;
+            for index in &schema.indexes {
+                self.migrate_integer_keys_tree(index.root_page_num, 0)?;
+                self.clear_tree(index.root_page_num, key::COMPOSITE_KEYS)?;
+                let entries = self.index_entries(&schema, index)?;
+                self.load_index(index, &entries)?;
+            }
+        }
+        self.pager.get_page(HEADER_PAGE_NUM)?.set_header_format_version(HEADER_FORMAT_VERSION);
+        self.pager.commit()?;
+        self.pager.checkpoint()
+    }
+
+    fn migrate_integer_keys_tree(&mut self, page_num: usize, depth: usize) -> Result<()> {
+        check_tree_depth(page_num, depth)?;
+        if self.pager.get_page_view(page_num)?.0[NODE_TYPE_OFFSET] != NODE_TYPE_INTERNAL {
+            return Ok(());
+        }
+        let page = self.pager.get_page(page_num)?;
+        if !page.migrate_integer_keys_internal_node() {
+            return Err(Error::Format(format!("Page {} does not fit the current format. Cannot migrate the file", page_num)));
+        }
+        for child_page_num in page.internal_node_children() {
+            self.migrate_integer_keys_tree(child_page_num, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// The schema described by a `create table` statement from the catalog.
+    fn parse_schema(sql: &str, root_page_num: usize) -> Result<Schema> {
+        let schema = match parser::parse(sql) {
+            Ok(ast::Statement::CreateTable(create)) => Schema::from_create(&create, root_page_num).ok(),
+            _ => None,
+        };
+        match schema {
+            Some(schema) => Ok(schema),
+            None => Err(Error::Corrupt(format!("Catalog entry {:?} is not a valid table", sql))),
+        }
+    }
+
+    /// Read the schema of every table, and the indexes on it, from the
+    /// catalog. An index is recorded after the table it belongs to.
+    fn load_catalog(&mut self) -> Result<()> {
+        let catalog = self.catalog.clone();
+        let mut rows = Vec::new();
+        self.scan_range(&catalog, &(Bound::Unbounded, Bound::Unbounded), &mut |row| {
+            rows.push(row);
+            true
+        })?;
+        let mut schemas: Vec<Schema> = Vec::new();
+        for row in rows {
+            let root_page_num = match row.values[catalog::CATALOG_ROOT_PAGE_COLUMN] {
+                Literal::Integer(root_page_num) => root_page_num as usize,
+                _ => 0,
+            };
+            let sql = match &row.values[catalog::CATALOG_SQL_COLUMN] {
+                Literal::Text(sql) => sql.clone(),
+                _ => String::new(),
+            };
+            if let Ok(ast::Statement::CreateIndex(create)) = parser::parse(&sql) {
+                let schema = schemas.iter_mut().find(|schema| schema.name == create.table);
+                match schema.and_then(|schema| Some((schema.column_index(&create.column)?, schema))) {
+                    Some((column, schema)) => schema.indexes.push(Index { name: create.name, column, unique: create.unique, root_page_num }),
+                    None => return Err(Error::Corrupt(format!("Catalog entry {:?} is not a valid index", sql))),
+                }
+                continue;
+            }
+            schemas.push(Table::parse_schema(&sql, root_page_num)?);
+        }
+        self.schemas = schemas;
+        Ok(())
+    }
+
+    fn schema(&self, name: &str) -> Option<&Schema> {
+        self.schemas.iter().find(|schema| schema.name == name)
+    }
+
+    /// What already goes by `name`, "Table" or "Index", if anything does.
+    /// Tables and indexes share one namespace.
+    fn name_kind(&self, name: &str) -> Option<&'static str> {
+        if self.schema(name).is_some() {
+            return Some("Table");
+        }
+        if self.schemas.iter().any(|schema| schema.indexes.iter().any(|index| index.name == name)) {
+            return Some("Index");
+        }
+        None
+    }
+
+    /// Root page of a new, empty tree holding keys of `format`.
+    fn create_tree(&mut self, format: KeyFormat) -> Result<usize> {
+        let root_page_num = self.pager.get_unused_page_num()?;
+        let root_node = self.pager.get_page(root_page_num)?;
+        root_node.initialize_leaf_node(format);
+        root_node.set_node_root(true);
+        Ok(root_page_num)
+    }
+
+    /// Free every page of a tree but its root, which becomes an empty leaf
+    /// holding keys of `format`.
+    fn clear_tree(&mut self, root_page_num: usize, format: KeyFormat) -> Result<()> {
+        self.free_subtrees(root_page_num, 0)?;
+        let root_node = self.pager.get_node(root_page_num)?;
+        root_node.initialize_leaf_node(format);
+        root_node.set_node_root(true);
+        Ok(())
+    }
+
+    /// Fill the empty tree rooted at `root_page_num` with `cells`, each a key
+    /// of `format` and its record, in key order, building it bottom-up rather
+    /// than splitting nodes one insert at a time. Leaves are packed to
+    /// `fill_percent` of their space and written one after another past the
+    /// end of the file, then each level of internal nodes above them, until
+    /// a level fits in the root page.
+    fn bulk_load(&mut self, root_page_num: usize, format: KeyFormat, cells: &[(Vec<u8>, Vec<u8>)], fill_percent: usize) -> Result<()> {
+        if cells.is_empty() {
+            return Ok(());
+        }
+        let layout = self.pager.layout;
+        let mut leaf_cells = Vec::with_capacity(cells.len());
+        for (key, record) in cells {
+            leaf_cells.push(self.pager.leaf_cell(key, record, format)?);
+        }
+        let groups = bulk_leaf_groups(&leaf_cells, &layout, fill_percent);
+        if groups.len() == 1 {
+            self.pager.get_node(root_page_num)?.set_leaf_node_cells(&leaf_cells);
+            return Ok(());
+        }
+
+        let first_page_num = self.pager.num_pages;
+        let mut children = Vec::with_capacity(groups.len());
+        let mut keys = Vec::with_capacity(groups.len());
+        for (i, group) in groups.iter().enumerate() {
+            let page_num = first_page_num + i;
+            let next_leaf = if i + 1 < groups.len() { page_num + 1 } else { 0 };
+            let leaf = self.pager.get_page(page_num)?;
+            leaf.initialize_leaf_node(format);
+            leaf.set_leaf_node_cells(&leaf_cells[group.clone()]);
+            leaf.set_leaf_node_next_leaf(next_leaf);
+            children.push(page_num);
+            keys.push(cells[group.end - 1].0.clone());
+        }
+
+        loop {
+            let groups = bulk_internal_groups(&keys, &layout, fill_percent);
+            let first_page_num = self.pager.num_pages;
+            let mut parents = Vec::with_capacity(groups.len());
+            let mut parent_keys = Vec::with_capacity(groups.len());
+            for (i, group) in groups.iter().enumerate() {
+                let page_num = if groups.len() == 1 { root_page_num } else { first_page_num + i };
+                let node = self.pager.get_page(page_num)?;
+                node.initialize_internal_node();
+                node.set_node_root(groups.len() == 1);
+                node.set_internal_node_cells(&children[group.clone()], &keys[group.start..group.end - 1]);
+                for &child in &children[group.clone()] {
+                    self.pager.get_page(child)?.set_node_parent(page_num);
+                }
+                parents.push(page_num);
+                parent_keys.push(keys[group.end - 1].clone());
+            }
+            if groups.len() == 1 {
+                return Ok(());
+            }
+            children = parents;
+            keys = parent_keys;
+        }
+    }
+
+    /// File `entries`, each a value and the primary key of its row, in an
+    /// index whose tree is empty, loading the tree in one pass.
+    fn load_index(&mut self, index: &Index, entries: &[(Literal, usize)]) -> Result<()> {
+        let mut cells: Vec<(Vec<u8>, Vec<u8>)> = entries.iter()
+            .filter(|(value, _)| *value != Literal::Null)
+            .map(|(value, key)| self.index_cell(value, *key))
+            .collect();
+        cells.sort_by(|(left, _), (right, _)| key::compare_composite_keys(left, right));
+        self.bulk_load(index.root_page_num, key::COMPOSITE_KEYS, &cells, BULK_LOAD_FILL_PERCENT)
+    }
+
+    /// Return the pages under a node, and the overflow pages of its cells, to
+    /// the free list. The node itself is left as it is.
+    fn free_subtrees(&mut self, page_num: usize, depth: usize) -> Result<()> {
+        check_tree_depth(page_num, depth)?;
+        let page = self.pager.get_node_view(page_num)?;
+        if page.is_leaf_node() {
+            for cell_num in 0..page.leaf_node_num_cells() {
+                self.pager.free_overflow(page_num, cell_num)?;
+            }
+            return Ok(());
+        }
+        for child_page_num in page.internal_node_children() {
+            self.free_subtrees(child_page_num, depth + 1)?;
+            self.pager.free_page(child_page_num)?;
+        }
+        Ok(())
+    }
+
+    /// Give a new table an empty tree and record it in the catalog, followed
+    /// by the indexes enforcing its unique columns.
+    fn create_table(&mut self, mut schema: Schema) -> Result<()> {
+        let indexes = mem::take(&mut schema.indexes);
+        schema.root_page_num = self.create_tree(key::INTEGER_KEYS)?;
+        self.add_catalog_row(&schema.name, schema.root_page_num, schema.to_sql())?;
+        self.schemas.push(schema.clone());
+        for index in indexes {
+            self.create_index(&schema, index)?;
+        }
+        Ok(())
+    }
+
+    /// Give a new index a tree, record it in the catalog and file the rows
+    /// already in its table. A unique index is refused before anything is
+    /// written if two rows hold the same value.
+    fn create_index(&mut self, schema: &Schema, mut index: Index) -> Result<()> {
+        let entries = self.index_entries(schema, &index)?;
+        if index.unique {
+            let values = entries.iter().map(|(value, key)| (value, *key)).collect();
+            if let Some((value, _)) = repeated_value(values) {
+                return Err(Error::Constraint(format!("Column {} holds {} more than once", schema.columns[index.column].name, value)));
+            }
+        }
+
+        index.root_page_num = self.create_tree(key::COMPOSITE_KEYS)?;
+        self.add_catalog_row(&index.name, index.root_page_num, schema.index_sql(&index))?;
+        self.load_index(&index, &entries)?;
+        if let Some(table_schema) = self.schemas.iter_mut().find(|table_schema| table_schema.name == schema.name) {
+            table_schema.indexes.push(index);
+        }
+        Ok(())
+    }
+
+    /// The value each row of a table holds in the column of an index, with
+    /// the row's primary key, in key order.
+    fn index_entries(&mut self, schema: &Schema, index: &Index) -> Result<Vec<(Literal, usize)>> {
+        let mut entries = Vec::new();
+        self.scan_range(schema, &(Bound::Unbounded, Bound::Unbounded), &mut |row| {
+            entries.push((row.values[index.column].clone(), row.key));
+            true
+        })?;
+        Ok(entries)
+    }
+
+    /// Record a table or index in the catalog, under the next free id.
+    fn add_catalog_row(&mut self, name: &str, root_page_num: usize, sql: String) -> Result<()> {
+        let catalog = self.catalog.clone();
+        let id = self.max_key(catalog.root_page_num)?.map_or(1, |id| id + 1);
+        let row = Row {
+            key: id,
+            values: vec![
+                Literal::Integer(id as i64),
+                Literal::Text(name.to_string()),
+                Literal::Integer(root_page_num as i64),
+                Literal::Text(sql),
+            ],
+        };
+        self.insert_row(&catalog, &row)
+    }
+
+    /// Close the database. A transaction still open is rolled back.
+    fn db_close(&mut self) -> Result<()> {
+        if self.in_transaction {
+            self.pager.rollback()?;
+        }
+        self.pager.pager_close()?;
+        Ok(())
+    }
+
+    /// Roll back the open transaction, explicit or not, after an error that
+    /// may have stopped a statement halfway.
+    fn abort(&mut self) -> Result<()> {
+        self.in_transaction = false;
+        self.pager.rollback()?;
+        self.load_catalog()
+    }
+
+    /// Commit the statement just executed unless it ran inside an explicit
+    /// transaction.
+    fn autocommit(&mut self) -> Result<()> {
+        if !self.in_transaction {
+            self.pager.commit()?;
+        }
+        Ok(())
+    }
+
+    fn execute_begin(&mut self) -> Result<()> {
+        if self.in_transaction {
+            return Err(Error::Invalid("A transaction is already open".to_string()));
+        }
+        self.in_transaction = true;
+        Ok(())
+    }
+
+    fn execute_commit(&mut self) -> Result<()> {
+        if !self.in_transaction {
+            return Err(Error::Invalid("No transaction is open".to_string()));
+        }
+        self.in_transaction = false;
+        self.pager.commit()?;
+        Ok(())
+    }
+
+    /// Discard the transaction, along with any tables it created.
+    fn execute_rollback(&mut self) -> Result<()> {
+        if !self.in_transaction {
+            return Err(Error::Invalid("No transaction is open".to_string()));
+        }
+        self.in_transaction = false;
+        self.pager.rollback()?;
+        self.load_catalog()?;
+        Ok(())
+    }
+
+    fn execute_create_table(&mut self, statement: &Statement) -> Result<()> {
+        self.create_table(statement.schema.clone())?;
+        Ok(())
+    }
+
+    fn execute_create_index(&mut self, statement: &Statement) -> Result<()> {
+        self.create_index(&statement.schema, statement.index.clone())?;
+        Ok(())
+    }
+
+    fn execute_insert(&mut self, statement : &mut Statement) -> Result<()> {
+        let schema = &statement.schema;
+        let row = &statement.row_to_insert;
+
+        let cursor = Cursor::find(self, schema.root_page_num, &key::integer_key(row.key), key::INTEGER_KEYS)?;
+        if !cursor.end_of_table {
+            return Err(Error::Constraint("Duplicate key".to_string()));
+        }
+        self.check_unique(schema, &row.values, row.key)?;
+        self.insert_row(schema, row)?;
+
+        Ok(())
+    }
+
+    /// Insert a row whose key is not in the table yet, and file it in the
+    /// table's indexes.
+    fn insert_row(&mut self, schema: &Schema, row: &Row) -> Result<()> {
+        let row_key = key::integer_key(row.key);
+        let mut cursor = Cursor::find(self, schema.root_page_num, &row_key, key::INTEGER_KEYS)?;
+        cursor.leaf_node_insert(&row_key, &schema.encode_record(&row.values))?;
+        for index in &schema.indexes {
+            self.index_insert(index, &row.values[index.column], row.key)?;
+        }
+        Ok(())
+    }
+
+    /// Load the records of a CSV file into a table, as one statement: every
+    /// record is checked before anything is written. An empty table, and
+    /// its indexes, are built bottom-up from the sorted rows; otherwise the
+    /// rows are inserted one by one. Returns the number of rows loaded.
+    fn import(&mut self, name: &str, records: Vec<csv::Record>) -> Result<usize> {
+        let schema = match self.schema(name) {
+            Some(schema) => schema.clone(),
+            None => return Err(Error::Invalid(format!("No such table: {}", name))),
+        };
+        let mut rows = Vec::with_capacity(records.len());
+        for record in records {
+            if record.fields.len() != schema.columns.len() {
+                let error = Error::Invalid(format!("Expected {} values, found {}", schema.columns.len(), record.fields.len()));
+                return Err(line_error(record.line, error));
+            }
+            let values = record.fields.into_iter().enumerate()
+                .map(|(column, field)| Statement::prepare_csv_value(&schema, column, field))
+                .collect::<Result<Vec<Literal>>>()
+                .map_err(|error| line_error(record.line, error))?;
+            let key = match values[schema.key_column] {
+                Literal::Integer(key) => key as usize,
+                _ => 0,
+            };
+            rows.push((record.line, Row { key, values }));
+        }
+
+        /* The sort is stable, so of two rows with the same key the later one is reported */
+        rows.sort_by_key(|(_, row)| row.key);
+        if let Some(pair) = rows.windows(2).find(|pair| pair[0].1.key == pair[1].1.key) {
+            return Err(line_error(pair[1].0, Error::Constraint("Duplicate key".to_string())));
+        }
+        for index in schema.indexes.iter().filter(|index| index.unique) {
+            let values = rows.iter().map(|(line, row)| (&row.values[index.column], *line)).collect();
+            if let Some((value, line)) = repeated_value(values) {
+                let error = Error::Constraint(format!("Column {} holds {} more than once", schema.columns[index.column].name, value));
+                return Err(line_error(line, error));
+            }
+        }
+
+        let root = self.pager.get_node_view(schema.root_page_num)?;
+        if root.is_leaf_node() && root.leaf_node_num_cells() == 0 {
+            let cells: Vec<(Vec<u8>, Vec<u8>)> = rows.iter()
+                .map(|(_, row)| (key::integer_key(row.key), schema.encode_record(&row.values)))
+                .collect();
+            self.bulk_load(schema.root_page_num, key::INTEGER_KEYS, &cells, BULK_LOAD_FILL_PERCENT)?;
+            for index in &schema.indexes {
+                let entries: Vec<(Literal, usize)> = rows.iter().map(|(_, row)| (row.values[index.column].clone(), row.key)).collect();
+                self.load_index(index, &entries)?;
+            }
+            return Ok(rows.len());
+        }
+
+        for (line, row) in &rows {
+            let cursor = Cursor::find(self, schema.root_page_num, &key::integer_key(row.key), key::INTEGER_KEYS)?;
+            if !cursor.end_of_table {
+                return Err(line_error(*line, Error::Constraint("Duplicate key".to_string())));
+            }
+            self.check_unique(&schema, &row.values, row.key).map_err(|error| line_error(*line, error))?;
+        }
+        for (_, row) in &rows {
+            self.insert_row(&schema, row)?;
+        }
+        Ok(rows.len())
+    }
+
+    /// Fail if the unique indexes of a table already file one of `values`
+    /// for a row other than the one with primary key `key`.
+    fn check_unique(&mut self, schema: &Schema, values: &[Literal], key: usize) -> Result<()> {
+        for index in schema.indexes.iter().filter(|index| index.unique) {
+            let value = &values[index.column];
+            if self.index_lookup(index, value)?.iter().any(|other_key| *other_key != key) {
+                return Err(Error::Constraint(format!("Unique column {} already holds {}", schema.columns[index.column].name, value)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The key filing the row with primary key `key` under `value` in an
+    /// index, and the record of its cell: empty, unless the key holds only the
+    /// start of the value, in which case the record holds all of it.
+    fn index_cell(&self, value: &Literal, key: usize) -> (Vec<u8>, Vec<u8>) {
+        let key_value = catalog::index_key_value(value, self.pager.layout.index_key_max_value_size);
+        let record = if key_value == *value { Vec::new() } else { key::composite_key(slice::from_ref(value)) };
+        (key::composite_key(&[key_value, Literal::Integer(key as i64)]), record)
+    }
+
+    /// File the row with primary key `key` under `value` in an index. NULLs
+    /// are left out, as no `=` comparison matches them.
+    fn index_insert(&mut self, index: &Index, value: &Literal, key: usize) -> Result<()> {
+        if *value == Literal::Null {
+            return Ok(());
+        }
+        let (index_key, record) = self.index_cell(value, key);
+        let mut cursor = Cursor::find(self, index.root_page_num, &index_key, key::COMPOSITE_KEYS)?;
+        if !cursor.end_of_table {
+            return Err(Error::Corrupt(format!("Index {} already lists key {}", index.name, key)));
+        }
+        cursor.leaf_node_insert(&index_key, &record)
+    }
+
+    /// Take the row with primary key `key` out of an index, where it is filed
+    /// under `value`.
+    fn index_delete(&mut self, index: &Index, value: &Literal, key: usize) -> Result<()> {
+        if *value == Literal::Null {
+            return Ok(());
+        }
+        let (index_key, _) = self.index_cell(value, key);
+        let mut cursor = Cursor::find(self, index.root_page_num, &index_key, key::COMPOSITE_KEYS)?;
+        if cursor.end_of_table {
+            return Err(Error::Corrupt(format!("Index {} does not list key {}", index.name, key)));
+        }
+        cursor.leaf_node_delete()
+    }
+
+    /// Primary keys of the rows an index files under `value`, in ascending
+    /// order. The entries for a value sit together in the tree, ordered by
+    /// primary key, behind those whose key value sorts before its own.
+    fn index_lookup(&mut self, index: &Index, value: &Literal) -> Result<Vec<usize>> {
+        let key_value = catalog::index_key_value(value, self.pager.layout.index_key_max_value_size);
+        let prefix = key::composite_key(slice::from_ref(&key_value));
+        let mut cursor = Cursor::table_seek(self, index.root_page_num, &prefix, key::COMPOSITE_KEYS)?;
+        let mut keys = Vec::new();
+        while !cursor.end_of_table {
+            let (entry_key_value, key, entry_value) = cursor.index_entry()?;
+            if planner::compare_literals(&entry_key_value, &key_value) != Ordering::Equal {
+                break;
+            }
+            /* Longer values sharing the start of this one share its key value */
+            if planner::compare_literals(&entry_value, value) == Ordering::Equal {
+                keys.push(key);
+            }
+            cursor.advance()?;
+        }
+        Ok(keys)
+    }
+
+    fn execute_delete(&mut self, statement : &Statement) -> Result<()> {
+        let key = statement.target_key;
+
+        let mut cursor = Cursor::find(self, statement.schema.root_page_num, &key::integer_key(key), key::INTEGER_KEYS)?;
+        if cursor.end_of_table {
+            return Err(Error::Invalid("Key not found".to_string()));
+        }
+        let row = cursor.value(&statement.schema)?;
+        cursor.leaf_node_delete()?;
+        for index in &statement.schema.indexes {
+            self.index_delete(index, &row.values[index.column], key)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute_update(&mut self, statement : &Statement) -> Result<()> {
+        let schema = &statement.schema;
+        let key = statement.target_key;
+
+        let mut cursor = Cursor::find(self, schema.root_page_num, &key::integer_key(key), key::INTEGER_KEYS)?;
+        if cursor.end_of_table {
+            return Err(Error::Invalid("Key not found".to_string()));
+        }
+        let mut row = cursor.value(schema)?;
+        let old_values = row.values.clone();
+        for (column, value) in &statement.assignments {
+            row.values[*column] = value.clone();
+        }
+        cursor.table.check_unique(schema, &row.values, key)?;
+        cursor.update_value(&schema.encode_record(&row.values))?;
+        for index in &schema.indexes {
+            let (old_value, new_value) = (&old_values[index.column], &row.values[index.column]);
+            if planner::compare_literals(old_value, new_value) != Ordering::Equal {
+                self.index_delete(index, old_value, key)?;
+                self.index_insert(index, new_value, key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The rows matching the statement's filter, in the requested order and
+    /// window.
+    ///
+    /// Bounds the filter places on the key let a scan seek to the first
+    /// candidate row and stop after the last one instead of reading every
+    /// leaf. Rows ordered by the key come straight off the tree, so a `limit`
+    /// stops the scan early; any other order is sorted in memory.
+    fn execute_select(&mut self, statement: &Statement) -> Result<Rows> {
+        let schema = &statement.schema;
+        let mut selected = Vec::new();
+        let key_column = schema.key_column_name();
+        let filter = statement.filter.as_ref();
+
+        let mut skip = statement.offset;
+        let mut remaining = statement.limit.unwrap_or(usize::MAX);
+        let mut emit = |row: Row| -> bool {
+            if remaining == 0 {
+                return false;
+            }
+            if skip > 0 {
+                skip -= 1;
+                return true;
+            }
+            selected.push(statement.projection.iter().map(|column| row.column_value(schema, column)).collect());
+            remaining -= 1;
+            remaining > 0
+        };
+
+        match statement.order_by.first() {
+            Some(order) if order.column == key_column && order.descending => {
+                self.scan(schema, filter, true, &mut |row| !row_matches(schema, filter, &row) || emit(row))?;
+            }
+            Some(order) if order.column != key_column => {
+                let mut rows = Vec::new();
+                self.scan(schema, filter, false, &mut |row| {
+                    if row_matches(schema, filter, &row) {
+                        rows.push(row);
+                    }
+                    true
+                })?;
+                rows.sort_by(|a, b| {
+                    for order in &statement.order_by {
+                        let ordering = planner::compare_literals(&a.column_value(schema, &order.column), &b.column_value(schema, &order.column));
+                        let ordering = if order.descending { ordering.reverse() } else { ordering };
+                        if ordering != Ordering::Equal {
+                            return ordering;
+                        }
+                    }
+                    Ordering::Equal
+                });
+                for row in rows {
+                    if !emit(row) {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                self.scan(schema, filter, false, &mut |row| !row_matches(schema, filter, &row) || emit(row))?;
+            }
+        }
+
+        Ok(Rows::new(statement.projection.clone(), selected))
+    }
+
+    /// The statement's aggregates over the matching rows, as one row.
+    ///
+    /// Over a whole table, `count(*)` only reads the cell count of each leaf,
+    /// and `min` and `max` of the key only the leftmost and rightmost leaves.
+    /// Anything else is folded over a scan of the rows the filter can match.
+    fn execute_aggregate(&mut self, statement: &Statement) -> Result<Rows> {
+        let schema = &statement.schema;
+        let root_page_num = schema.root_page_num;
+        let mut aggregates: Vec<Aggregate> = statement.aggregates.iter()
+            .map(|(function, column)| Aggregate::new(*function, column.clone()))
+            .collect();
+
+        if statement.filter.is_none() && aggregates.iter().all(|aggregate| aggregate.answered_by_tree(schema.key_column_name())) {
+            for aggregate in aggregates.iter_mut() {
+                match aggregate.function {
+                    AggregateFunction::Count => {
+                        aggregate.count = self.count_rows(root_page_num)?;
+                    }
+                    AggregateFunction::Min => {
+                        aggregate.min = self.min_key(root_page_num)?.map(|key| Literal::Integer(key as i64));
+                        aggregate.count = aggregate.min.is_some() as usize;
+                    }
+                    _ => {
+                        aggregate.max = self.max_key(root_page_num)?.map(|key| Literal::Integer(key as i64));
+                        aggregate.count = aggregate.max.is_some() as usize;
+                    }
+                }
+            }
+        } else {
+            let filter = statement.filter.as_ref();
+            self.scan(schema, filter, false, &mut |row| {
+                if row_matches(schema, filter, &row) {
+                    for aggregate in aggregates.iter_mut() {
+                        aggregate.add(&|column| row.column_value(schema, column));
+                    }
+                }
+                true
+            })?;
+        }
+
+        let columns = aggregates.iter().map(|aggregate| aggregate.name()).collect();
+        let mut selected = Vec::new();
+        if statement.offset == 0 && statement.limit != Some(0) {
+            selected.push(aggregates.iter().map(|aggregate| aggregate.result()).collect());
+        }
+        Ok(Rows::new(columns, selected))
+    }
+
+    /// Number of rows in a tree, summed from the leaf headers along the leaf
+    /// chain.
+    fn count_rows(&mut self, root_page_num: usize) -> Result<usize> {
+        let mut count = 0;
+        let mut page_num = self.pager.get_leftmost_leaf_page_num(root_page_num)?;
+        let mut num_leaves = 1;
+        loop {
+            self.pager.check_leaf_chain(page_num, num_leaves)?;
+            let page = self.pager.get_node_view(page_num)?;
+            count += page.leaf_node_num_cells();
+            page_num = page.get_leaf_node_next_leaf();
+            if page_num == 0 {
+                return Ok(count);
+            }
+            num_leaves += 1;
+        }
+    }
+
+    /// Smallest key: the first cell of the leftmost leaf.
+    fn min_key(&mut self, root_page_num: usize) -> Result<Option<usize>> {
+        let page_num = self.pager.get_leftmost_leaf_page_num(root_page_num)?;
+        let page = self.pager.get_node_view(page_num)?;
+        if page.leaf_node_num_cells() == 0 {
+            return Ok(None);
+        }
+        Ok(Some(page.leaf_node_integer_key(0)))
+    }
+
+    /// Largest key: the last cell of the rightmost leaf.
+    fn max_key(&mut self, root_page_num: usize) -> Result<Option<usize>> {
+        let page_num = self.pager.get_rightmost_leaf_page_num(root_page_num)?;
+        let page = self.pager.get_node_view(page_num)?;
+        let num_cells = page.leaf_node_num_cells();
+        if num_cells == 0 {
+            return Ok(None);
+        }
+        Ok(Some(page.leaf_node_integer_key(num_cells - 1)))
+    }
+
+    /// Visit the rows `filter` may hold for, in ascending or descending key
+    /// order, until `visit` returns false; the filter itself is left to
+    /// `visit`. If the filter requires a column with an index to equal some
+    /// value, the index lists the rows to read. Otherwise the rows in the
+    /// filter's key range are read from the table's tree.
+    fn scan(&mut self, schema: &Schema, filter: Option<&Expr>, descending: bool, visit: &mut dyn FnMut(Row) -> bool) -> Result<()> {
+        let range = planner::key_range(filter, schema.key_column_name());
+        let mut keys = match self.index_scan_keys(schema, filter)? {
+            Some(keys) => keys,
+            None if descending => {
+                self.scan_range_desc(schema, schema.root_page_num, 0, &range, visit)?;
+                return Ok(());
+            }
+            None => return self.scan_range(schema, &range, visit),
+        };
+        if descending {
+            keys.reverse();
+        }
+        for key in keys.into_iter().filter(|key| range.contains(key)) {
+            let mut cursor = Cursor::find(self, schema.root_page_num, &key::integer_key(key), key::INTEGER_KEYS)?;
+            if cursor.end_of_table {
+                return Err(Error::Corrupt(format!("Index on {} lists key {} missing from the table", schema.name, key)));
+            }
+            let row = cursor.value(schema)?;
+            if !visit(row) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keys of the rows an index lists for `filter`, if the filter requires
+    /// an indexed column to equal a value of the column's type.
+    fn index_scan_keys(&mut self, schema: &Schema, filter: Option<&Expr>) -> Result<Option<Vec<usize>>> {
+        for index in &schema.indexes {
+            let column = &schema.columns[index.column];
+            match planner::equality_value(filter, &column.name) {
+                Some(value) if column.column_type.is_type_of(&value) => return Ok(Some(self.index_lookup(index, &value)?)),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Visit the rows of a table with keys in `range` in ascending key order,
+    /// following the leaf chain, until `visit` returns false.
+    fn scan_range(&mut self, schema: &Schema, range: &KeyRange, visit: &mut dyn FnMut(Row) -> bool) -> Result<()> {
+        let mut cursor = match range.0 {
+            Bound::Included(key) | Bound::Excluded(key) => Cursor::table_seek(self, schema.root_page_num, &key::integer_key(key), key::INTEGER_KEYS)?,
+            Bound::Unbounded => Cursor::table_start(self, schema.root_page_num, key::INTEGER_KEYS)?,
+        };
+        while !cursor.end_of_table {
+            let row = cursor.value(schema)?;
+            if planner::past_end(range, row.key) {
+                break;
+            }
+            if range.contains(&row.key) && !visit(row) {
+                break;
+            }
+            cursor.advance()?;
+        }
+        Ok(())
+    }
+
+    /// Visit the rows with keys in `range` under `page_num` in descending key
+    /// order until `visit` returns false. Leaves only link forward, so this
+    /// walks the tree right to left, skipping subtrees outside the range.
+    /// Returns false once the scan has stopped.
+    fn scan_range_desc(&mut self, schema: &Schema, page_num: usize, depth: usize, range: &KeyRange, visit: &mut dyn FnMut(Row) -> bool) -> Result<bool> {
+        check_tree_depth(page_num, depth)?;
+        let page = self.pager.get_node_view(page_num)?;
+        if page.is_leaf_node() {
+            for cell_num in (0..page.leaf_node_num_cells()).rev() {
+                let row = self.pager.leaf_node_row(schema, page_num, cell_num)?;
+                if planner::before_start(range, row.key) {
+                    return Ok(false);
+                }
+                if range.contains(&row.key) && !visit(row) {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+
+        let children = page.internal_node_children();
+        let keys: Vec<usize> = page.internal_node_keys().iter().map(|key| key::integer_key_value(key)).collect();
+        for child_index in (0..children.len()).rev() {
+            /* Child i holds the keys in (keys[i - 1], keys[i]] */
+            if child_index > 0 && planner::past_end(range, keys[child_index - 1] + 1) {
+                continue;
+            }
+            if child_index < keys.len() && planner::before_start(range, keys[child_index]) {
+                return Ok(false);
+            }
+            if !self.scan_range_desc(schema, children[child_index], depth + 1, range, visit)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn internal_node_find(&mut self, page_num: usize, depth: usize, key: &[u8], format: KeyFormat) -> Result<(usize, usize)> {
+        let page = self.pager.get_node_view(page_num)?;
+        let child_page_num = page.get_internal_node_child(page.internal_node_find_child(key, format.compare))?;
+        self.find_by_page_num(child_page_num, depth + 1, key, format)
+    }
+
+    fn leaf_node_find(&mut self, page_num: usize, key: &[u8], format: KeyFormat) -> Result<(usize, usize)> {
+        let page = self.pager.get_node_view(page_num)?;
+        Ok((page_num, page.leaf_node_find(key, format.compare)))
+    }
+
+    fn find_by_page_num(&mut self, page_num: usize, depth: usize, key: &[u8], format: KeyFormat) -> Result<(usize, usize)> {
+        check_tree_depth(page_num, depth)?;
+        match self.pager.get_node_view(page_num)?.get_node_type() {
+            NodeInternal => {
+                Ok(self.internal_node_find(page_num, depth, key, format)?)
+            }
+            NodeLeaf => {
+                Ok(self.leaf_node_find(page_num, key, format)?)
+            }
+        }
+    }
+
+    /// Take `new_page_num` into the parent of `page_num`, which was split in
+    /// two: `page_num` keeps the keys up to `separator` and the new node the
+    /// ones after, up to the bound the parent held for `page_num`.
+    fn internal_node_insert(&mut self, parent_page_num: usize, page_num: usize, separator: &[u8], new_page_num: usize) -> Result<()> {
+        self.pager.get_node(new_page_num)?.set_node_parent(parent_page_num);
+        let parent = self.pager.get_node(parent_page_num)?;
+        let child_index = parent.internal_node_child_index(page_num);
+        if parent.internal_node_used_space() + internal_cell_size(separator) + INTERNAL_NODE_SLOT_SIZE <= parent.layout().internal_node_space_for_cells {
+            parent.internal_node_insert_cell(child_index, page_num, separator);
+            parent.set_internal_node_child(child_index + 1, new_page_num)?;
+            return Ok(());
+        }
+
+        let mut children = parent.internal_node_children();
+        let mut keys = parent.internal_node_keys();
+        keys.insert(child_index, separator.to_vec());
+        children.insert(child_index + 1, new_page_num);
+        self.internal_node_split_and_insert(parent_page_num, &children, &keys)
+    }
+
+    /// Write `children` and `keys`, which no longer fit in one page, to the
+    /// internal node `page_num`. The node keeps the lower half of them and a
+    /// new node takes the upper half, while the key between the halves goes
+    /// up to the parent. A root stays on its page, above the two halves.
+    fn internal_node_split_and_insert(&mut self, page_num: usize, children: &[usize], keys: &[Vec<u8>]) -> Result<()> {
+        let middle = internal_split_point(keys);
+        let new_page_num = self.pager.get_unused_page_num()?;
+        let new_node = self.pager.get_page(new_page_num)?;
+        new_node.initialize_internal_node();
+        new_node.set_internal_node_cells(&children[middle + 1..], &keys[middle + 1..]);
+        self.pager.get_node(page_num)?.set_internal_node_cells(&children[..=middle], &keys[..middle]);
+        for (i, &child) in children.iter().enumerate() {
+            let parent_page_num = if i <= middle { page_num } else { new_page_num };
+            self.pager.get_node(child)?.set_node_parent(parent_page_num);
+        }
+
+        let node = self.pager.get_node_view(page_num)?;
+        if node.is_node_root() {
+            return self.create_new_node(page_num, new_page_num);
+        }
+        let parent_page_num = node.get_node_parent();
+        self.internal_node_insert(parent_page_num, page_num, &keys[middle], new_page_num)
+    }
+
+    /// Replace the key of cell `cell_num` of an internal node, splitting the
+    /// node if a longer key no longer fits.
+    fn set_internal_node_key(&mut self, page_num: usize, cell_num: usize, key: &[u8]) -> Result<()> {
+        let node = self.pager.get_node(page_num)?;
+        if node.internal_node_used_space() - node.internal_node_cell_size(cell_num) + internal_cell_size(key) <= node.layout().internal_node_space_for_cells {
+            node.set_internal_node_key(cell_num, key);
+            return Ok(());
+        }
+
+        let children = node.internal_node_children();
+        let mut keys = node.internal_node_keys();
+        keys[cell_num] = key.to_vec();
+        self.internal_node_split_and_insert(page_num, &children, &keys)
+    }
+
+    /// The max key of `page_num` dropped to `new_max`. Update the separator
+    /// in the closest ancestor that stores it, i.e. the first one reached
+    /// through a child other than its right child.
+    fn update_max_key(&mut self, mut page_num: usize, new_max: &[u8]) -> Result<()> {
+        let mut height = 0;
+        while !self.pager.get_node_view(page_num)?.is_node_root() {
+            check_tree_depth(page_num, height)?;
+            height += 1;
+            let parent_page_num = self.pager.get_node_view(page_num)?.get_node_parent();
+            let parent = self.pager.get_node_view(parent_page_num)?;
+            let child_index = parent.internal_node_child_index(page_num);
+            if child_index < parent.get_internal_node_num_keys() {
+                return self.set_internal_node_key(parent_page_num, child_index, new_max);
+            }
+            page_num = parent_page_num;
+        }
+        Ok(())
+    }
+
+    /// Levels of nodes in a tree, from its root down to its leaves.
+    fn tree_depth(&mut self, root_page_num: usize) -> Result<usize> {
+        let mut page_num = root_page_num;
+        let mut depth = 1;
+        while !self.pager.get_node_view(page_num)?.is_leaf_node() {
+            page_num = self.pager.get_node_view(page_num)?.get_internal_node_child(0)?;
+            check_tree_depth(page_num, depth)?;
+            depth += 1;
+        }
+        Ok(depth)
+    }
+
+    /// Restore the minimum fill of a node after a cell was removed from it, by
+    /// merging it with a sibling or borrowing cells from one. Merging removes a
+    /// cell from the parent, so this recurses upwards.
+    fn rebalance(&mut self, page_num: usize) -> Result<()> {
+        let node = self.pager.get_node_view(page_num)?;
+        if node.is_node_root() {
+            if !node.is_leaf_node() && node.get_internal_node_num_keys() == 0 {
+                self.collapse_root(page_num)?;
+            }
+            return Ok(());
+        }
+
+        let is_leaf = node.is_leaf_node();
+        let underfull = if is_leaf {
+            node.leaf_node_used_space() < node.layout().leaf_node_min_fill
+        } else {
+            node.internal_node_used_space() < node.layout().internal_node_min_fill
+        };
+        if !underfull {
+            return Ok(());
+        }
+
+        let parent_page_num = node.get_node_parent();
+        let child_index = self.pager.get_node_view(parent_page_num)?.internal_node_child_index(page_num);
+        /* Pair the node with its left sibling, or its right sibling if it is the leftmost child */
+        let left_index = if child_index > 0 { child_index - 1 } else { 0 };
+
+        if is_leaf {
+            self.rebalance_leaf_nodes(parent_page_num, left_index)?;
+        } else {
+            self.rebalance_internal_nodes(parent_page_num, left_index)?;
+        }
+        Ok(())
+    }
+
+    /// Merge or redistribute the leaves at `left_index` and `left_index + 1` of the parent.
+    fn rebalance_leaf_nodes(&mut self, parent_page_num: usize, left_index: usize) -> Result<()> {
+        let parent = self.pager.get_node_view(parent_page_num)?;
+        let left_page_num = parent.get_internal_node_child(left_index)?;
+        let right_page_num = parent.get_internal_node_child(left_index + 1)?;
+
+        let mut cells = self.pager.get_node_view(left_page_num)?.leaf_node_cells();
+        cells.extend(self.pager.get_node_view(right_page_num)?.leaf_node_cells());
+        let size: usize = cells.iter().map(|cell| cell.len() + LEAF_NODE_SLOT_SIZE).sum();
+
+        if size <= self.pager.layout.leaf_node_space_for_cells {
+            let next_leaf = self.pager.get_node_view(right_page_num)?.get_leaf_node_next_leaf();
+            let left = self.pager.get_node(left_page_num)?;
+            left.set_leaf_node_cells(&cells);
+            left.set_leaf_node_next_leaf(next_leaf);
+            self.remove_merged_child(parent_page_num, left_index, left_page_num)?;
+        } else {
+            let left_count = leaf_split_point(&cells);
+            let left = self.pager.get_node(left_page_num)?;
+            left.set_leaf_node_cells(&cells[..left_count]);
+            let left_max = left.leaf_node_key(left_count - 1).to_vec();
+            self.pager.get_node(right_page_num)?.set_leaf_node_cells(&cells[left_count..]);
+            self.set_internal_node_key(parent_page_num, left_index, &left_max)?;
+        }
+        Ok(())
+    }
+
+    /// Merge or redistribute the internal nodes at `left_index` and `left_index + 1`
+    /// of the parent. The parent's separator key moves down between the two
+    /// nodes' cells.
+    fn rebalance_internal_nodes(&mut self, parent_page_num: usize, left_index: usize) -> Result<()> {
+        let parent = self.pager.get_node_view(parent_page_num)?;
+        let left_page_num = parent.get_internal_node_child(left_index)?;
+        let right_page_num = parent.get_internal_node_child(left_index + 1)?;
+        let separator = parent.get_internal_node_key(left_index).to_vec();
+
+        let left = self.pager.get_node_view(left_page_num)?;
+        let mut children = left.internal_node_children();
+        let mut keys = left.internal_node_keys();
+        keys.push(separator);
+        let right = self.pager.get_node_view(right_page_num)?;
+        children.extend(right.internal_node_children());
+        keys.extend(right.internal_node_keys());
+
+        if internal_cells_size(&keys) <= self.pager.layout.internal_node_space_for_cells {
+            self.pager.get_node(left_page_num)?.set_internal_node_cells(&children, &keys);
+            for &child in &children {
+                self.pager.get_node(child)?.set_node_parent(left_page_num);
+            }
+            self.remove_merged_child(parent_page_num, left_index, left_page_num)?;
+        } else {
+            let middle = internal_split_point(&keys);
+            self.pager.get_node(left_page_num)?.set_internal_node_cells(&children[..=middle], &keys[..middle]);
+            self.pager.get_node(right_page_num)?.set_internal_node_cells(&children[middle + 1..], &keys[middle + 1..]);
+            for (i, &child) in children.iter().enumerate() {
+                let new_parent = if i <= middle { left_page_num } else { right_page_num };
+                self.pager.get_node(child)?.set_node_parent(new_parent);
+            }
+            self.set_internal_node_key(parent_page_num, left_index, &keys[middle])?;
+        }
+        Ok(())
+    }
+
+    /// The right sibling of child `left_index` was merged into it. The merged
+    /// node takes over the sibling's slot, whose key still bounds it, and the
+    /// left cell is dropped. The sibling's page goes to the free list.
+    fn remove_merged_child(&mut self, parent_page_num: usize, left_index: usize, left_page_num: usize) -> Result<()> {
+        let parent = self.pager.get_node(parent_page_num)?;
+        let right_page_num = parent.get_internal_node_child(left_index + 1)?;
+        parent.set_internal_node_child(left_index + 1, left_page_num)?;
+        parent.internal_node_remove_cell(left_index);
+        self.pager.free_page(right_page_num)?;
+        self.rebalance(parent_page_num)?;
+        Ok(())
+    }
+
+    /// The root is an internal node left with a single child: pull that child
+    /// up into the root page so the tree loses a level.
+    fn collapse_root(&mut self, root_page_num: usize) -> Result<()> {
+        let root = self.pager.get_node_view(root_page_num)?;
+        let child_page_num = root.get_internal_node_child(root.get_internal_node_num_keys())?;
+        let child = self.pager.get_node_view(child_page_num)?.clone();
+
+        let root = self.pager.get_node(root_page_num)?;
+        *root = child;
+        root.set_node_root(true);
+        if !root.is_leaf_node() {
+            for grandchild in root.internal_node_children() {
+                self.pager.get_node(grandchild)?.set_node_parent(root_page_num);
+            }
+        }
+        self.pager.free_page(child_page_num)?;
+        Ok(())
+    }
+
+    /// The shape of a tree, one node or key per line, children indented
+    /// under their parent.
+    fn format_tree(&mut self, root_page_num: usize, format: KeyFormat) -> Result<String> {
+        fn format_tree_node(pager: &mut Pager, page_num: usize, format: KeyFormat, indentation_level: usize, out: &mut String) -> Result<()> {
+            check_tree_depth(page_num, indentation_level)?;
+            let indent = |level: usize| " ".repeat(level);
+            match pager.get_node_view(page_num)?.get_node_type() {
+                NodeLeaf => {
+                    let num_keys = pager.get_node_view(page_num)?.leaf_node_num_cells();
+                    out.push_str(&format!("{}- leaf (size {})\n", indent(indentation_level), num_keys));
+                    for i in 0..num_keys {
+                        out.push_str(&format!("{}{}\n", indent(indentation_level + 1), (format.format)(pager.get_node_view(page_num)?.leaf_node_key(i))));
+                    }
+                },
+                NodeInternal => {
+                    let num_keys = pager.get_node_view(page_num)?.get_internal_node_num_keys();
+                    out.push_str(&format!("{}- internal (size {})\n", indent(indentation_level), num_keys));
+                    for i in 0..num_keys {
+                        let child = pager.get_node_view(page_num)?.get_internal_node_child(i)?;
+                        format_tree_node(pager, child, format, indentation_level + 1, out)?;
+                        out.push_str(&format!("{}- key {}\n", indent(indentation_level + 1), (format.format)(pager.get_node_view(page_num)?.get_internal_node_key(i))));
+                    }
+                    let child = pager.get_node_view(page_num)?.get_internal_node_child(num_keys)?;
+                    format_tree_node(pager, child, format, indentation_level + 1, out)?;
+                }
+            }
+            Ok(())
+        }
+
+        let mut out = String::new();
+        format_tree_node(&mut self.pager, root_page_num, format, 0, &mut out)?;
+        Ok(out)
+    }
+
+    fn create_new_node(&mut self, root_page_num: usize, right_child_page_num: usize) -> Result<()> {
+        // create new root node
+        self.pager.get_page(right_child_page_num)?;
+        let left_child_page_num = self.pager.get_unused_page_num()?;
+        let node_max_key;
+        {
+            let old_node = self.pager.get_node_view(root_page_num)?.clone();
+            let left_child = self.pager.get_page(left_child_page_num)?;
+            *left_child = old_node;
+            left_child.set_node_root(false);
+            node_max_key = self.pager.get_node_max_key(left_child_page_num)?;
+        }
+        match self.pager.get_node(left_child_page_num)?.get_node_type() {
+            NodeInternal => {
+                for i in 0..=self.pager.get_node(left_child_page_num)?.get_internal_node_num_keys() {
+                    let inode_child = self.pager.get_node(left_child_page_num)?.get_internal_node_child(i)?;
+                    self.pager.get_node(inode_child)?.set_node_parent(left_child_page_num);
+                }
+            }
+            NodeLeaf => {}
+        }
+
+        let old_node = self.pager.get_node(root_page_num)?;
+        old_node.initialize_internal_node();
+        old_node.set_node_root(true);
+        old_node.set_internal_node_cells(&[left_child_page_num, right_child_page_num], &[node_max_key]);
+
+        {
+            let left_child = self.pager.get_node(left_child_page_num)?;
+            left_child.set_node_parent(root_page_num);
+        }
+        {
+            let right_child = self.pager.get_page(right_child_page_num)?;
+            right_child.set_node_parent(root_page_num);
+        }
+        Ok(())
+    }
+
+}
+
+struct Cursor<'a> {
+    table : &'a mut Table,
+    page_num : usize,
+    cell_num : usize,
+    end_of_table : bool,
+    /// How the keys of the tree the cursor walks are ordered.
+    format : KeyFormat,
+    /// Leaves the cursor has stood on, so that a leaf chain looping back on
+    /// itself is caught.
+    num_leaves : usize,
+}
+
+impl <'a> Cursor<'a> {
+    fn table_start(table: &'a mut Table, root_page_num: usize, format: KeyFormat) -> Result<Cursor<'a>> {
+        let leaf_page_num = table.pager.get_leftmost_leaf_page_num(root_page_num)?;
+        let root_node = table.pager.get_node_view(leaf_page_num)?;
+        let num_cells = root_node.leaf_node_num_cells();
+        Ok(Cursor {
+            table,
+            page_num : leaf_page_num,
+            cell_num : 0,
+            end_of_table: num_cells == 0,
+            format,
+            num_leaves: 1,
+        })
+    }
+
+    fn table_find(table: &'a mut Table, root_page_num: usize, key: &[u8], format: KeyFormat) -> Result<(usize, usize)> {
+        let root_node = table.pager.get_node_view(root_page_num)?;
+        match root_node.get_node_type() {
+            NodeType::NodeInternal => {
+                table.internal_node_find(root_page_num, 0, key, format)
+            }
+            NodeType::NodeLeaf => {
+                table.leaf_node_find(root_page_num, key, format)
+            }
+        }
+    }
+
+    /// Position a cursor on the cell a tree holds under `key`. If there is
+    /// none, `end_of_table` is set and the cursor stands where the cell
+    /// would be inserted.
+    fn find(table: &'a mut Table, root_page_num: usize, key: &[u8], format: KeyFormat) -> Result<Cursor<'a>> {
+        let (page_num, cell_num) = Cursor::table_find(table, root_page_num, key, format)?;
+        let page = table.pager.get_node_view(page_num)?;
+        let found = cell_num < page.leaf_node_num_cells()
+            && (format.compare)(page.leaf_node_key(cell_num), key) == Ordering::Equal;
+        Ok(Cursor {
+            table,
+            page_num,
+            cell_num,
+            end_of_table: !found,
+            format,
+            num_leaves: 1,
+        })
+    }
+
+    /// Position a cursor on the first cell whose key is `key` or greater.
+    fn table_seek(table: &'a mut Table, root_page_num: usize, key: &[u8], format: KeyFormat) -> Result<Cursor<'a>> {
+        let (page_num, cell_num) = Cursor::table_find(table, root_page_num, key, format)?;
+        let mut cursor = Cursor {
+            table,
+            page_num,
+            cell_num,
+            end_of_table: false,
+            format,
+            num_leaves: 1,
+        };
+        let page = cursor.table.pager.get_node_view(page_num)?;
+        if cell_num >= page.leaf_node_num_cells() {
+            /* Every key in this leaf is smaller, so the answer starts the next leaf */
+            let next_page_num = page.get_leaf_node_next_leaf();
+            if next_page_num == 0 {
+                cursor.end_of_table = true;
+            } else {
+                cursor.page_num = next_page_num;
+                cursor.cell_num = 0;
+                cursor.num_leaves += 1;
+            }
+        }
+        Ok(cursor)
+    }
+
+    fn value(&mut self, schema: &Schema) -> Result<Row> {
+        self.table.pager.leaf_node_row(schema, self.page_num, self.cell_num)
+    }
+
+    /// The index cell under the cursor: the value it is filed under as it
+    /// stands in the key, the primary key of its row, and the whole value.
+    fn index_entry(&mut self) -> Result<(Literal, usize, Literal)> {
+        let (index_key, record) = self.table.pager.leaf_node_record(self.page_num, self.cell_num)?;
+        let values = key::composite_key_values(&index_key);
+        let full_value = if record.is_empty() { None } else { key::composite_key_values(&record) };
+        match (values.as_deref(), full_value.as_deref()) {
+            (Some([key_value, Literal::Integer(key)]), None) if record.is_empty() && *key >= 0 => {
+                Ok((key_value.clone(), *key as usize, key_value.clone()))
+            }
+            (Some([key_value, Literal::Integer(key)]), Some([value])) if *key >= 0 => {
+                Ok((key_value.clone(), *key as usize, value.clone()))
+            }
+            _ => Err(Error::Corrupt(format!("Page {}: malformed index entry {}", self.page_num, (self.format.format)(&index_key)))),
+        }
+    }
+
+    fn advance(&mut self) -> Result<()> {
+        let page = self.table.pager.get_node_view(self.page_num)?;
+        self.cell_num += 1;
+        if self.cell_num >= page.leaf_node_num_cells() {
+            let next_page_num = page.get_leaf_node_next_leaf();
+            if next_page_num == 0 {
+                /* This was rightmost leaf */
+                self.end_of_table = true;
+            } else {
+                self.num_leaves += 1;
+                self.table.pager.check_leaf_chain(next_page_num, self.num_leaves)?;
+                self.page_num = next_page_num;
+                self.cell_num = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn leaf_node_insert(&mut self, key: &[u8], record: &[u8]) -> Result<()> {
+        let max_key_size = self.table.pager.layout.max_key_size;
+        if key.len() > max_key_size {
+            return Err(Error::Invalid(format!("A key of {} bytes is longer than the {} bytes a key may take", key.len(), max_key_size)));
+        }
+        let cell = self.table.pager.leaf_cell(key, record, self.format)?;
+        let page = self.table.pager.get_node(self.page_num)?;
+        if !page.leaf_node_fits(cell.len()) {
+            self.leaf_node_split_and_insert(cell)?;
+            return Ok(());
+        }
+        page.leaf_node_insert_cell(self.cell_num, &cell);
+        Ok(())
+    }
+
+    fn leaf_node_split_and_insert(& mut self, cell: Vec<u8>) -> Result<()> {
+        /*
+         Create a new node and move half the cells over.
+         Insert the new value in one of the two nodes.
+         Update parent or create a new parent.
+        */
+        let new_page_num = self.table.pager.get_unused_page_num()?;
+        let old_node_parent_num = self.table.pager.get_node(self.page_num)?.get_node_parent();
+        self.table.pager.get_page(new_page_num)?.initialize_leaf_node(self.format);
+        self.table.pager.get_node(new_page_num)?.set_node_parent(old_node_parent_num);
+        let old_next_page_num = self.table.pager.get_node(self.page_num)?.get_leaf_node_next_leaf();
+        self.table.pager.get_node(new_page_num)?.set_leaf_node_next_leaf(old_next_page_num);
+        self.table.pager.get_node(self.page_num)?.set_leaf_node_next_leaf(new_page_num);
+        /*
+         All existing cells plus the new one are divided between old (left)
+         and new (right) nodes so that each holds about half of their bytes.
+        */
+        let mut cells = self.table.pager.get_node_view(self.page_num)?.leaf_node_cells();
+        cells.insert(self.cell_num, cell);
+        let left_count = leaf_split_point(&cells);
+        self.table.pager.get_node(self.page_num)?.set_leaf_node_cells(&cells[..left_count]);
+        self.table.pager.get_node(new_page_num)?.set_leaf_node_cells(&cells[left_count..]);
+        let is_node_root = self.table.pager.get_node(self.page_num)?.is_node_root();
+
+        if is_node_root {
+            return self.table.create_new_node(self.page_num, new_page_num);
+        } else {
+            let old_node = self.table.pager.get_node(self.page_num)?;
+            let parent_page_num = old_node.get_node_parent();
+            let new_max = old_node.leaf_node_key(left_count - 1).to_vec();
+            self.table.internal_node_insert(parent_page_num, self.page_num, &new_max, new_page_num)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the record under the cursor; the key is unchanged. A record
+    /// that no longer fits in the leaf splits it.
+    fn update_value(&mut self, record: &[u8]) -> Result<()> {
+        self.table.pager.free_overflow(self.page_num, self.cell_num)?;
+        let page = self.table.pager.get_node(self.page_num)?;
+        let key = page.leaf_node_key(self.cell_num).to_vec();
+        page.leaf_node_remove_cell(self.cell_num);
+        self.leaf_node_insert(&key, record)?;
+        Ok(())
+    }
+
+    fn leaf_node_delete(&mut self) -> Result<()> {
+        self.table.pager.free_overflow(self.page_num, self.cell_num)?;
+        let page = self.table.pager.get_node(self.page_num)?;
+        let num_cells = page.leaf_node_num_cells();
+        page.leaf_node_remove_cell(self.cell_num);
+
+        /* Removing the last cell lowers the max key, which ancestors may hold as a separator */
+        if self.cell_num == num_cells - 1 && num_cells > 1 {
+            let new_max = page.leaf_node_key(num_cells - 2).to_vec();
+            self.table.update_max_key(self.page_num, &new_max)?;
+        }
+        self.table.rebalance(self.page_num)?;
+        Ok(())
+    }
+
+    // fn create_new_node(&mut self, right_child_page_num: usize) {
+    //     // create new root node
+    //     let left_child_page_num = self.table.pager.get_unused_page_num();
+    //     let mut node_max_key;
+    //     {
+    //         let old_node = self.table.pager.get_page(self.page_num);
+    //         let old_node_ptr = old_node as *const Page;
+    //         let left_child = self.table.pager.get_page(left_child_page_num);
+    //         unsafe {
+    //             std::ptr::copy(old_node_ptr as *const u8, left_child as *mut Page as *mut u8, PAGE_SIZE);
+    //             left_child.set_node_root(false);
+    //         }
+    //         node_max_key = self.table.pager.get_node_max_key(left_child_page_num);
+    //     }
+    //
+    //     let old_node = self.table.pager.get_page(self.page_num);
+    //     old_node.initialize_internal_node();
+    //     old_node.set_node_root(true);
+    //     old_node.set_internal_node_num_keys(1);
+    //     old_node.set_internal_node_child(0, left_child_page_num);
+    //     old_node.set_internal_node_key(0, node_max_key);
+    //     old_node.set_internal_node_right_child(right_child_page_num);
+    //
+    //     let root_page_num = self.table.root_page_num;
+    //     {
+    //         let left_child = self.table.pager.get_page(left_child_page_num);
+    //         left_child.set_node_parent(root_page_num);
+    //     }
+    //     {
+    //         let right_child = self.table.pager.get_page(right_child_page_num);
+    //         right_child.set_node_parent(root_page_num);
+    //     }
+    // }
+}
+
+fn row_matches(schema: &Schema, filter: Option<&Expr>, row: &Row) -> bool {
+    match filter {
+        Some(filter) => planner::is_true(&planner::eval(filter, &|column| row.column_value(schema, column))),
+        None => true,
+    }
+}
+
+impl Statement {
+    fn new() -> Self {
+        Self {
+            kind: StatementNone,
+            schema: Schema::default(),
+            index: Index::default(),
+            row_to_insert : Row {
+                key: 0,
+                values: Vec::new(),
+            },
+            target_key: 0,
+            assignments: Vec::new(),
+            projection: Vec::new(),
+            aggregates: Vec::new(),
+            filter: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: 0,
+        }
+    }
+
+    fn prepare_create_table(&mut self, create: CreateTable, table: &Table) -> Result<()> {
+        self.kind = StatementCreateTable;
+        if let Some(kind) = table.name_kind(&create.name) {
+            return Err(Error::Invalid(format!("{} {} already exists", kind, create.name)));
+        }
+        self.schema = match Schema::from_create(&create, 0) {
+            Ok(schema) => schema,
+            Err(SchemaError::UnknownType(type_name)) => return Err(Error::Invalid(format!("Unknown column type: {}", type_name))),
+            Err(SchemaError::DuplicateColumn(column)) => return Err(Error::Invalid(format!("Duplicate column name: {}", column))),
+            Err(SchemaError::MissingKey) => return Err(Error::Invalid("A table needs exactly one INTEGER PRIMARY KEY column".to_string())),
+        };
+        /* Each unique column but the key, which is unique anyway, gets an index enforcing it */
+        for def in create.columns.iter().filter(|def| def.unique && !def.primary_key) {
+            let name = format!("{}_{}_unique", create.name, def.name);
+            if let Some(kind) = table.name_kind(&name) {
+                return Err(Error::Invalid(format!("{} {} already exists", kind, name)));
+            }
+            let column = self.schema.column_index(&def.name).unwrap();
+            self.schema.indexes.push(Index { name, column, unique: true, root_page_num: 0 });
+        }
+        Ok(())
+    }
+
+    fn prepare_create_index(&mut self, create: CreateIndex, table: &Table) -> Result<()> {
+        self.kind = StatementCreateIndex;
+        if let Some(kind) = table.name_kind(&create.name) {
+            return Err(Error::Invalid(format!("{} {} already exists", kind, create.name)));
+        }
+        self.schema = match table.schema(&create.table) {
+            Some(schema) => schema.clone(),
+            None => return Err(Error::Invalid(format!("No such table: {}", create.table))),
+        };
+        let column = match self.schema.column_index(&create.column) {
+            Some(column) => column,
+            None => return Err(Error::Invalid(format!("No such column: {}", create.column))),
+        };
+        self.index = Index { name: create.name, column, unique: create.unique, root_page_num: 0 };
+        Ok(())
+    }
+
+    fn prepare_insert(&mut self, insert: Insert, table: &Table) -> Result<()> {
+        self.kind = StatementInsert;
+        self.schema = match table.schema(&insert.table) {
+            Some(schema) => schema.clone(),
+            None => return Err(Error::Invalid(format!("No such table: {}", insert.table))),
+        };
+        let schema = &self.schema;
+        let columns = match insert.columns {
+            Some(columns) => columns,
+            None => schema.column_names(),
+        };
+        if columns.len() != insert.values.len() {
+            return Err(Error::Invalid(format!("Expected {} values, found {}", columns.len(), insert.values.len())));
+        }
+
+        let mut values: Vec<Option<Literal>> = vec![None; schema.columns.len()];
+        for (column, value) in columns.into_iter().zip(insert.values) {
+            let index = match schema.column_index(&column) {
+                Some(index) => index,
+                None => return Err(Error::Invalid(format!("No such column: {}", column))),
+            };
+            values[index] = Some(Statement::prepare_value(schema, index, value)?);
+        }
+        let key = match values[schema.key_column] {
+            Some(Literal::Integer(key)) => key as usize,
+            _ => return Err(Error::Invalid(format!("Column {} expects INTEGER", schema.key_column_name()))),
+        };
+        /* Columns left out hold NULL */
+        let values = values.into_iter().map(|value| value.unwrap_or(Literal::Null)).collect();
+        self.row_to_insert = Row { key, values };
+
+        Ok(())
+    }
+
+    fn prepare_delete(&mut self, delete: Delete, table: &Table) -> Result<()> {
+        self.kind = StatementDelete;
+        self.schema = match table.schema(&delete.table) {
+            Some(schema) => schema.clone(),
+            None => return Err(Error::Invalid(format!("No such table: {}", delete.table))),
+        };
+        self.target_key = Statement::prepare_filter_key(&self.schema, delete.filter)?;
+        Ok(())
+    }
+
+    fn prepare_update(&mut self, update: Update, table: &Table) -> Result<()> {
+        self.kind = StatementUpdate;
+        self.schema = match table.schema(&update.table) {
+            Some(schema) => schema.clone(),
+            None => return Err(Error::Invalid(format!("No such table: {}", update.table))),
+        };
+        let schema = &self.schema;
+        self.target_key = Statement::prepare_filter_key(schema, update.filter)?;
+
+        for (column, value) in update.assignments {
+            let index = match schema.column_index(&column) {
+                Some(index) => index,
+                None => return Err(Error::Invalid(format!("No such column: {}", column))),
+            };
+            if index == schema.key_column {
+                return Err(Error::Invalid("Changing the primary key of a row is not supported".to_string()));
+            }
+            self.assignments.push((index, Statement::prepare_value(schema, index, value)?));
+        }
+        Ok(())
+    }
+
+    fn prepare_select(&mut self, select: Select, table: &Table) -> Result<()> {
+        self.kind = StatementSelect;
+        self.schema = match table.schema(&select.table) {
+            Some(schema) => schema.clone(),
+            None => return Err(Error::Invalid(format!("No such table: {}", select.table))),
+        };
+        let schema = &self.schema;
+
+        for item in select.projection {
+            match item {
+                SelectItem::Wildcard => {
+                    self.projection.extend(schema.column_names());
+                }
+                SelectItem::Column(column) => {
+                    self.projection.push(column);
+                }
+                SelectItem::Aggregate(function, column) => {
+                    self.aggregates.push((function, column));
+                }
+            }
+        }
+        if !self.projection.is_empty() && !self.aggregates.is_empty() {
+            return Err(Error::Invalid("Aggregates cannot be selected together with plain columns".to_string()));
+        }
+        for (function, column) in &self.aggregates {
+            let summed = matches!(function, AggregateFunction::Sum | AggregateFunction::Avg);
+            let column = column.as_deref().and_then(|column| schema.column_index(column)).map(|index| &schema.columns[index]);
+            match column {
+                Some(column) if summed && !matches!(column.column_type, ColumnType::Integer | ColumnType::Real) => {
+                    return Err(Error::Invalid(format!("Column {} expects {} or {}", column.name, ColumnType::Integer.name(), ColumnType::Real.name())));
+                }
+                _ => {}
+            }
+        }
+
+        let mut columns: Vec<&str> = self.projection.iter().map(|column| column.as_str()).collect();
+        columns.extend(self.aggregates.iter().filter_map(|(_, column)| column.as_deref()));
+        columns.extend(select.order_by.iter().map(|order| order.column.as_str()));
+        if let Some(filter) = &select.filter {
+            columns.extend(planner::expr_columns(filter));
+        }
+        if let Some(column) = columns.into_iter().find(|column| schema.column_index(column).is_none()) {
+            return Err(Error::Invalid(format!("No such column: {}", column)));
+        }
+
+        self.filter = select.filter;
+        self.order_by = select.order_by;
+        self.limit = select.limit;
+        self.offset = select.offset.unwrap_or(0);
+        Ok(())
+    }
+
+    /// A primary key value: a non-negative integer.
+    fn prepare_key(schema: &Schema, value: Literal) -> Result<usize> {
+        match value {
+            Literal::Integer(key) if key < 0 => Err(Error::NegativeKey),
+            Literal::Integer(key) => Ok(key as usize),
+            _ => Err(Error::Invalid(format!("Column {} expects INTEGER", schema.key_column_name()))),
+        }
+    }
+
+    /// A value for the column at `column`, converted to the column's type.
+    /// Any column but the key may be NULL. Integers widen to REAL, 0 and 1
+    /// stand for the booleans, and numbers given for text are stored as
+    /// their decimal text.
+    fn prepare_value(schema: &Schema, column: usize, value: Literal) -> Result<Literal> {
+        if column == schema.key_column {
+            return Statement::prepare_key(schema, value).map(|key| Literal::Integer(key as i64));
+        }
+        let column = &schema.columns[column];
+        let value = match (column.column_type, value) {
+            (_, Literal::Null) => Literal::Null,
+            (ColumnType::Integer, Literal::Integer(value)) => Literal::Integer(value),
+            (ColumnType::Real, Literal::Integer(value)) => Literal::Real(value as f64),
+            (ColumnType::Real, Literal::Real(value)) => Literal::Real(value),
+            (ColumnType::Text, Literal::Text(value)) => Literal::Text(value),
+            (ColumnType::Text, Literal::Integer(value)) => Literal::Text(value.to_string()),
+            (ColumnType::Text, Literal::Real(value)) => Literal::Text(value.to_string()),
+            (ColumnType::Blob, Literal::Blob(value)) => Literal::Blob(value),
+            (ColumnType::Boolean, Literal::Boolean(value)) => Literal::Boolean(value),
+            (ColumnType::Boolean, Literal::Integer(value @ (0 | 1))) => Literal::Boolean(value == 1),
+            _ => return Err(Error::Invalid(format!("Column {} expects {}", column.name, column.column_type.name()))),
+        };
+        if let Literal::Text(text) = &value {
+            if column.max_length.is_some_and(|max_length| text.len() > max_length) {
+                return Err(Error::StringTooLong);
+            }
+        }
+        Ok(value)
+    }
+
+    /// A field of an imported CSV record as a value of the column at
+    /// `column`. Its text is read as the column's type, a blob as hex
+    /// digits, and the value is then checked like an inserted one.
+    fn prepare_csv_value(schema: &Schema, column: usize, field: csv::Field) -> Result<Literal> {
+        let text = match field {
+            Some(text) => text,
+            None => return Statement::prepare_value(schema, column, Literal::Null),
+        };
+        let value = match schema.columns[column].column_type {
+            ColumnType::Text => Literal::Text(text),
+            ColumnType::Blob => match csv::hex_bytes(&text) {
+                Some(bytes) => Literal::Blob(bytes),
+                None => Literal::Text(text),
+            },
+            ColumnType::Integer | ColumnType::Real | ColumnType::Boolean => match text.as_str() {
+                "true" => Literal::Boolean(true),
+                "false" => Literal::Boolean(false),
+                _ => match (text.parse::<i64>(), text.parse::<f64>()) {
+                    (Ok(value), _) => Literal::Integer(value),
+                    (_, Ok(value)) => Literal::Real(value),
+                    _ => Literal::Text(text),
+                },
+            },
+        };
+        Statement::prepare_value(schema, column, value)
+    }
+
+    /// The key named by a `where <key> = <n>` filter, the only filter
+    /// supported for now.
+    fn prepare_filter_key(schema: &Schema, filter: Option<Expr>) -> Result<usize> {
+        if let Some(Expr::Compare(CompareOp::Equal, left, right)) = filter {
+            match (*left, *right) {
+                (Expr::Column(column), Expr::Literal(value)) | (Expr::Literal(value), Expr::Column(column)) => {
+                    if column == schema.key_column_name() {
+                        return Statement::prepare_key(schema, value);
+                    }
+                    if schema.column_index(&column).is_none() {
+                        return Err(Error::Invalid(format!("No such column: {}", column)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(Error::Invalid("Only WHERE <primary key> = <n> is supported here".to_string()))
+    }
+
+    fn prepare_statement(&mut self, sql: &str, table: &Table) -> Result<()> {
+        let statement = parser::parse(sql)?;
+
+        match statement {
+            ast::Statement::Insert(insert) => {
+                return self.prepare_insert(insert, table);
+            }
+            ast::Statement::Delete(delete) => {
+                return self.prepare_delete(delete, table);
+            }
+            ast::Statement::Update(update) => {
+                return self.prepare_update(update, table);
+            }
+            ast::Statement::Select(select) => {
+                return self.prepare_select(select, table);
+            }
+            ast::Statement::CreateTable(create) => {
+                return self.prepare_create_table(create, table);
+            }
+            ast::Statement::CreateIndex(create) => {
+                return self.prepare_create_index(create, table);
+            }
+            ast::Statement::Begin => {
+                self.kind = StatementBegin;
+            }
+            ast::Statement::Commit => {
+                self.kind = StatementCommit;
+            }
+            ast::Statement::Rollback => {
+                self.kind = StatementRollback;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the statement; only a `select` returns rows.
+    fn execute_statement(&mut self, table : &mut Table) -> Result<Rows> {
+        match self.kind {
+            StatementInsert => {
+                table.execute_insert(self)?;
+            }
+            StatementSelect if !self.aggregates.is_empty() => {
+                return table.execute_aggregate(self);
+            }
+            StatementSelect => {
+                return table.execute_select(self);
+            }
+            StatementDelete => {
+                table.execute_delete(self)?;
+            }
+            StatementUpdate => {
+                table.execute_update(self)?;
+            }
+            StatementCreateTable => {
+                table.execute_create_table(self)?;
+            }
+            StatementCreateIndex => {
+                table.execute_create_index(self)?;
+            }
+            StatementBegin => {
+                table.execute_begin()?;
+            }
+            StatementCommit => {
+                table.execute_commit()?;
+            }
+            StatementRollback => {
+                table.execute_rollback()?;
+            }
+
+            _ => {}
+        }
+        Ok(Rows::new(Vec::new(), Vec::new()))
+    }
+}
+
+/// Settings a database is opened with.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Pages kept in the buffer pool; at least 4.
+    pub cache_frames: usize,
+    /// Size in bytes of the pages of a database created by opening it, a
+    /// power of two from `MIN_PAGE_SIZE` to `MAX_PAGE_SIZE`. A database that
+    /// already exists keeps its own.
+    pub page_size: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            cache_frames: PAGER_DEFAULT_MAX_FRAMES,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// An open database file.
+///
+/// Changes are durable once the statement making them commits, so a
+/// database dropped without `close` loses nothing but an open transaction;
+/// its log is replayed the next time the file is opened.
+pub struct Database {
+    table: Table,
+}
+
+impl Database {
+    /// Open the database at `path`, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Database> {
+        Database::open_with(path, Options::default())
+    }
+
+    pub fn open_with(path: &str, options: Options) -> Result<Database> {
+        if !valid_page_size(options.page_size) {
+            return Err(Error::Invalid(format!("Page size {} is not a power of two from {} to {}",
+                                              options.page_size, MIN_PAGE_SIZE, MAX_PAGE_SIZE)));
+        }
+        let table = Table::db_open(path, options.cache_frames, options.page_size)?;
+        Ok(Database { table })
+    }
+
+    /// A connection to run statements through.
+    pub fn connect(&mut self) -> Connection<'_> {
+        Connection { table: &mut self.table }
+    }
+
+    /// Checkpoint the log into the file and close it. A transaction still
+    /// open is rolled back.
+    pub fn close(mut self) -> Result<()> {
+        self.table.db_close()
+    }
+}
+
+/// A session on a database, with at most one open transaction.
+pub struct Connection<'a> {
+    table: &'a mut Table,
+}
+
+/// Sizes reported by `Connection::stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Pages in the file, the header page included.
+    pub pages: usize,
+    /// Pages on the free list.
+    pub free_pages: usize,
+}
+
+impl Connection<'_> {
+    /// Run one statement. Outside a transaction it commits before this
+    /// returns. If the file fails or turns out corrupt halfway through, the
+    /// open transaction is rolled back before the error is returned.
+    pub fn execute(&mut self, sql: &str) -> Result<Rows> {
+        let mut statement = Statement::new();
+        statement.prepare_statement(sql, self.table)?;
+
+        let result = statement.execute_statement(self.table);
+        self.finish(result)
+    }
+
+    /// Load the CSV file at `path` into a table, a record per row with a
+    /// field per column in the table's order, as a single statement. An
+    /// empty table is built bottom-up, which is much faster than inserting
+    /// its rows. Returns the number of rows loaded.
+    pub fn import(&mut self, path: &str, table: &str) -> Result<usize> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => return Err(Error::Invalid(format!("Cannot read {}: {}", path, error))),
+        };
+        let records = match csv::parse_records(&text) {
+            Ok(records) => records,
+            Err(error) => return Err(Error::Invalid(format!("Line {}: {}", error.line, error.message))),
+        };
+        let result = self.table.import(table, records);
+        self.finish(result)
+    }
+
+    /// Commit a statement just run, or roll it back if it may have stopped
+    /// halfway.
+    fn finish<T>(&mut self, result: Result<T>) -> Result<T> {
+        let result = match result {
+            /* A statement cut short by the file may be half done; nothing of it is kept */
+            Err(error) if error.is_fatal() => Err(error),
+            /* Outside a transaction every statement commits on its own, before its result is reported */
+            result => self.table.autocommit().and(result),
+        };
+        match result {
+            Err(error) if error.is_fatal() => self.table.abort().and(Err(error)),
+            result => result,
+        }
+    }
+
+    /// Names of the tables, in the order they were created.
+    pub fn tables(&self) -> Vec<String> {
+        self.table.schemas.iter().map(|schema| schema.name.clone()).collect()
+    }
+
+    pub fn stats(&mut self) -> Result<Stats> {
+        Ok(Stats {
+            pages: self.table.pager.num_pages,
+            free_pages: self.table.pager.num_free_pages()?,
+        })
+    }
+
+    /// The B-tree of a table or index as printed by `.btree`.
+    pub fn tree(&mut self, name: &str) -> Result<String> {
+        let (root_page_num, format) = self.find_tree(name)?;
+        self.table.format_tree(root_page_num, format)
+    }
+
+    /// Levels of nodes in the tree of a table or index, the leaves included.
+    pub fn tree_depth(&mut self, name: &str) -> Result<usize> {
+        let (root_page_num, _) = self.find_tree(name)?;
+        self.table.tree_depth(root_page_num)
+    }
+
+    /// The root page and key format of the tree of a table or index.
+    fn find_tree(&self, name: &str) -> Result<(usize, KeyFormat)> {
+        let tree = self.table.schemas.iter().find_map(|schema| {
+            if schema.name == name {
+                return Some((schema.root_page_num, key::INTEGER_KEYS));
+            }
+            schema.indexes.iter().find(|index| index.name == name).map(|index| (index.root_page_num, key::COMPOSITE_KEYS))
+        });
+        match tree {
+            Some(tree) => Ok(tree),
+            None => Err(Error::Invalid(format!("No such table: {}", name))),
+        }
+    }
+}
+
+/// The rows returned by a statement, each a value per column. They are read
+/// while the statement runs, so the connection is free for the next one.
+pub struct Rows {
+    columns: Vec<String>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl Rows {
+    fn new(columns: Vec<String>, rows: Vec<Vec<Value>>) -> Rows {
+        Rows { columns, rows: rows.into_iter() }
+    }
+
+    /// Names of the columns, such as `id` or `count(*)`.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Vec<Value>;
+
+    fn next(&mut self) -> Option<Vec<Value>> {
+        self.rows.next()
+    }
+}
@@ -0,0 +1,523 @@
+//! Recursive-descent parser from a line of SQL to a [`Statement`].
+//!
+//! Besides standard SQL it accepts the shorthand forms the REPL started
+//! with, all addressing the `users` table: `insert 1 user1 a@example.com`,
+//! `select`, `delete 1` and `update 1 set username=alice email=a@example.com`.
+//! Their values are bare runs of characters, lexed by `tokenize_shorthand`.
+//! Keywords are case-insensitive and unquoted identifiers are folded to
+//! lower case.
+
+use crate::ast::{AggregateFunction, ColumnDef, CompareOp, CreateIndex, CreateTable, Delete, Expr, Insert, Literal, OrderBy, Select, SelectItem, Statement, Update};
+use crate::lexer::{tokenize, tokenize_shorthand, SyntaxError, Token, TokenKind};
+
+/// Table addressed by the shorthand statement forms.
+pub const DEFAULT_TABLE: &str = "users";
+
+const KEYWORDS: &[&str] = &[
+    "and", "asc", "begin", "between", "by", "commit", "create", "delete", "desc", "false", "from", "index",
+    "insert", "into", "is", "key", "limit", "not", "null", "offset", "on", "or", "order", "primary", "rollback",
+    "select", "set", "table", "transaction", "true", "unique", "update", "values", "where",
+];
+
+pub fn parse(input: &str) -> Result<Statement, SyntaxError> {
+    let tokens = if is_shorthand_with_values(input) { tokenize_shorthand(input)? } else { tokenize(input)? };
+    let mut parser = Parser { tokens, pos: 0 };
+    let statement = parser.parse_statement()?;
+    parser.eat(&TokenKind::Semicolon);
+    if parser.peek().kind != TokenKind::Eof {
+        return parser.error("end of statement");
+    }
+    Ok(statement)
+}
+
+/// Whether a line is a shorthand `insert` or `update`, whose values are
+/// lexed as bare runs of characters rather than as SQL.
+fn is_shorthand_with_values(input: &str) -> bool {
+    let mut words = input.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(first), Some(second)) if first.eq_ignore_ascii_case("insert") => {
+            !second.get(..4).is_some_and(|start| start.eq_ignore_ascii_case("into"))
+        }
+        (Some(first), Some(second)) if first.eq_ignore_ascii_case("update") => {
+            second.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        }
+        _ => false,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Report that `expected` was wanted where the next token stands.
+    fn error<T>(&self, expected: &str) -> Result<T, SyntaxError> {
+        let token = self.peek();
+        Err(SyntaxError {
+            position: token.position,
+            message: format!("expected {}, found {}", expected, token.kind),
+        })
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SyntaxError> {
+        if self.eat_keyword(keyword) {
+            return Ok(());
+        }
+        self.error(&keyword.to_uppercase())
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if &self.peek().kind == kind {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), SyntaxError> {
+        if self.eat(kind) {
+            return Ok(());
+        }
+        self.error(&kind.to_string())
+    }
+
+    fn at_end(&self) -> bool {
+        matches!(self.peek().kind, TokenKind::Eof | TokenKind::Semicolon)
+    }
+
+    fn parse_identifier(&mut self, what: &str) -> Result<String, SyntaxError> {
+        if let TokenKind::Word(word) = &self.peek().kind {
+            let name = word.to_lowercase();
+            let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if valid && !KEYWORDS.contains(&name.as_str()) {
+                self.advance();
+                return Ok(name);
+            }
+        }
+        self.error(what)
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, SyntaxError> {
+        let keyword = match &self.peek().kind {
+            TokenKind::Word(word) => word.to_lowercase(),
+            _ => return self.error("a statement"),
+        };
+        match keyword.as_str() {
+            "insert" => self.parse_insert(),
+            "select" => self.parse_select(),
+            "update" => self.parse_update(),
+            "delete" => self.parse_delete(),
+            "create" => self.parse_create(),
+            "begin" => {
+                self.advance();
+                self.eat_keyword("transaction");
+                Ok(Statement::Begin)
+            }
+            "commit" => {
+                self.advance();
+                Ok(Statement::Commit)
+            }
+            "rollback" => {
+                self.advance();
+                Ok(Statement::Rollback)
+            }
+            _ => self.error("a statement"),
+        }
+    }
+
+    /// `INSERT INTO table [(column, ...)] VALUES (value, ...)`, or the
+    /// shorthand `insert value ...`.
+    fn parse_insert(&mut self) -> Result<Statement, SyntaxError> {
+        self.advance();
+        if !self.eat_keyword("into") {
+            let mut values = vec![self.parse_bare_value()?];
+            while !self.at_end() {
+                values.push(self.parse_bare_value()?);
+            }
+            return Ok(Statement::Insert(Insert { table: DEFAULT_TABLE.to_string(), columns: None, values }));
+        }
+
+        let table = self.parse_identifier("a table name")?;
+        let mut columns = None;
+        if self.eat(&TokenKind::LeftParen) {
+            let mut names = vec![self.parse_identifier("a column name")?];
+            while self.eat(&TokenKind::Comma) {
+                names.push(self.parse_identifier("a column name")?);
+            }
+            self.expect(&TokenKind::RightParen)?;
+            columns = Some(names);
+        }
+
+        self.expect_keyword("values")?;
+        self.expect(&TokenKind::LeftParen)?;
+        let mut values = vec![self.parse_literal()?];
+        while self.eat(&TokenKind::Comma) {
+            values.push(self.parse_literal()?);
+        }
+        self.expect(&TokenKind::RightParen)?;
+        Ok(Statement::Insert(Insert { table, columns, values }))
+    }
+
+    /// `SELECT * | column, ... FROM table [WHERE expr] [ORDER BY column
+    /// [ASC | DESC], ...] [LIMIT n] [OFFSET n]`, or the shorthand `select`
+    /// followed directly by any of the clauses.
+    fn parse_select(&mut self) -> Result<Statement, SyntaxError> {
+        self.advance();
+        let shorthand = self.at_end() || ["where", "order", "limit", "offset"].iter().any(|keyword| self.is_keyword(keyword));
+        let (table, projection) = if shorthand {
+            (DEFAULT_TABLE.to_string(), vec![SelectItem::Wildcard])
+        } else {
+            let mut projection = vec![self.parse_select_item()?];
+            while self.eat(&TokenKind::Comma) {
+                projection.push(self.parse_select_item()?);
+            }
+            self.expect_keyword("from")?;
+            (self.parse_identifier("a table name")?, projection)
+        };
+        let filter = self.parse_where()?;
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let column = self.parse_identifier("a column name")?;
+                let descending = self.eat_keyword("desc");
+                if !descending {
+                    self.eat_keyword("asc");
+                }
+                order_by.push(OrderBy { column, descending });
+                if !self.eat(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let mut limit = None;
+        if self.eat_keyword("limit") {
+            limit = Some(self.parse_count()?);
+        }
+        let mut offset = None;
+        if self.eat_keyword("offset") {
+            offset = Some(self.parse_count()?);
+        }
+        Ok(Statement::Select(Select { table, projection, filter, order_by, limit, offset }))
+    }
+
+    /// `*`, a column, or an aggregate `count(*)`, `count(column)`,
+    /// `min(column)`, `max(column)`, `sum(column)` or `avg(column)`.
+    fn parse_select_item(&mut self) -> Result<SelectItem, SyntaxError> {
+        if self.eat(&TokenKind::Star) {
+            return Ok(SelectItem::Wildcard);
+        }
+        let function = match &self.peek().kind {
+            TokenKind::Word(word) if self.tokens[self.pos + 1].kind == TokenKind::LeftParen => {
+                match word.to_lowercase().as_str() {
+                    "count" => Some(AggregateFunction::Count),
+                    "min" => Some(AggregateFunction::Min),
+                    "max" => Some(AggregateFunction::Max),
+                    "sum" => Some(AggregateFunction::Sum),
+                    "avg" => Some(AggregateFunction::Avg),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let function = match function {
+            Some(function) => function,
+            None => return Ok(SelectItem::Column(self.parse_identifier("'*' or a column name")?)),
+        };
+
+        self.advance();
+        self.expect(&TokenKind::LeftParen)?;
+        let column = if function == AggregateFunction::Count && self.eat(&TokenKind::Star) {
+            None
+        } else {
+            Some(self.parse_identifier("a column name")?)
+        };
+        self.expect(&TokenKind::RightParen)?;
+        Ok(SelectItem::Aggregate(function, column))
+    }
+
+    /// A non-negative integer, as taken by `LIMIT`, `OFFSET` and type sizes.
+    fn parse_count(&mut self) -> Result<usize, SyntaxError> {
+        if let TokenKind::Integer(value) = self.peek().kind {
+            self.advance();
+            return Ok(value as usize);
+        }
+        self.error("a non-negative integer")
+    }
+
+    /// `UPDATE table SET column = value, ... [WHERE expr]`, or the shorthand
+    /// `update id set column=value ...`.
+    fn parse_update(&mut self) -> Result<Statement, SyntaxError> {
+        self.advance();
+        if self.is_shorthand_key() {
+            let filter = self.parse_shorthand_key()?;
+            self.expect_keyword("set")?;
+            let mut assignments = Vec::new();
+            loop {
+                let column = self.parse_identifier("a column name")?;
+                self.expect(&TokenKind::Equal)?;
+                assignments.push((column, self.parse_bare_value()?));
+                self.eat(&TokenKind::Comma);
+                if self.at_end() {
+                    break;
+                }
+            }
+            return Ok(Statement::Update(Update { table: DEFAULT_TABLE.to_string(), assignments, filter: Some(filter) }));
+        }
+
+        let table = self.parse_identifier("a table name")?;
+        self.expect_keyword("set")?;
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.parse_identifier("a column name")?;
+            self.expect(&TokenKind::Equal)?;
+            assignments.push((column, self.parse_literal()?));
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+        let filter = self.parse_where()?;
+        Ok(Statement::Update(Update { table, assignments, filter }))
+    }
+
+    /// `DELETE FROM table [WHERE expr]`, or the shorthand `delete id`.
+    fn parse_delete(&mut self) -> Result<Statement, SyntaxError> {
+        self.advance();
+        if self.is_shorthand_key() {
+            let filter = self.parse_shorthand_key()?;
+            return Ok(Statement::Delete(Delete { table: DEFAULT_TABLE.to_string(), filter: Some(filter) }));
+        }
+
+        self.expect_keyword("from")?;
+        let table = self.parse_identifier("a table name")?;
+        let filter = self.parse_where()?;
+        Ok(Statement::Delete(Delete { table, filter }))
+    }
+
+    fn parse_create(&mut self) -> Result<Statement, SyntaxError> {
+        self.advance();
+        if self.eat_keyword("unique") {
+            self.expect_keyword("index")?;
+            return self.parse_create_index(true);
+        }
+        if self.eat_keyword("index") {
+            return self.parse_create_index(false);
+        }
+        if self.eat_keyword("table") {
+            return self.parse_create_table();
+        }
+        self.error("TABLE or INDEX")
+    }
+
+    /// `CREATE [UNIQUE] INDEX name ON table (column)`, after `INDEX`.
+    fn parse_create_index(&mut self, unique: bool) -> Result<Statement, SyntaxError> {
+        let name = self.parse_identifier("an index name")?;
+        self.expect_keyword("on")?;
+        let table = self.parse_identifier("a table name")?;
+        self.expect(&TokenKind::LeftParen)?;
+        let column = self.parse_identifier("a column name")?;
+        self.expect(&TokenKind::RightParen)?;
+        Ok(Statement::CreateIndex(CreateIndex { name, unique, table, column }))
+    }
+
+    /// `CREATE TABLE name (column type[(size)] [PRIMARY KEY] [UNIQUE], ...)`, after
+    /// `CREATE TABLE`.
+    fn parse_create_table(&mut self) -> Result<Statement, SyntaxError> {
+        let name = self.parse_identifier("a table name")?;
+        self.expect(&TokenKind::LeftParen)?;
+        let mut columns = Vec::new();
+        loop {
+            let column_name = self.parse_identifier("a column name")?;
+            let type_name = self.parse_identifier("a column type")?;
+            let mut size = None;
+            if self.eat(&TokenKind::LeftParen) {
+                size = Some(self.parse_count()?);
+                self.expect(&TokenKind::RightParen)?;
+            }
+            let mut primary_key = false;
+            let mut unique = false;
+            loop {
+                if self.eat_keyword("primary") {
+                    self.expect_keyword("key")?;
+                    primary_key = true;
+                } else if self.eat_keyword("unique") {
+                    unique = true;
+                } else {
+                    break;
+                }
+            }
+            columns.push(ColumnDef { name: column_name, type_name, size, primary_key, unique });
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RightParen)?;
+        Ok(Statement::CreateTable(CreateTable { name, columns }))
+    }
+
+    fn is_shorthand_key(&self) -> bool {
+        matches!(self.peek().kind, TokenKind::Integer(_) | TokenKind::Minus)
+    }
+
+    /// The key of a shorthand `update`/`delete`, as the filter `id = key`.
+    fn parse_shorthand_key(&mut self) -> Result<Expr, SyntaxError> {
+        let key = self.parse_literal()?;
+        Ok(Expr::Compare(CompareOp::Equal, Box::new(Expr::Column("id".to_string())), Box::new(Expr::Literal(key))))
+    }
+
+    fn parse_where(&mut self) -> Result<Option<Expr>, SyntaxError> {
+        if self.eat_keyword("where") {
+            return Ok(Some(self.parse_expr()?));
+        }
+        Ok(None)
+    }
+
+    /// A number, optionally negated, a quoted string, a blob, `true`, `false`
+    /// or `null`.
+    fn parse_literal(&mut self) -> Result<Literal, SyntaxError> {
+        let value = match self.peek().kind.clone() {
+            TokenKind::Integer(value) => Literal::Integer(value),
+            TokenKind::Real(value) => Literal::Real(value),
+            TokenKind::Minus => {
+                self.advance();
+                match self.peek().kind {
+                    TokenKind::Integer(value) => Literal::Integer(-value),
+                    TokenKind::Real(value) => Literal::Real(-value),
+                    _ => return self.error("a number"),
+                }
+            }
+            TokenKind::Str(value) => Literal::Text(value),
+            TokenKind::Blob(value) => Literal::Blob(value),
+            _ if self.is_keyword("null") => Literal::Null,
+            _ if self.is_keyword("true") => Literal::Boolean(true),
+            _ if self.is_keyword("false") => Literal::Boolean(false),
+            _ => return self.error("a value"),
+        };
+        self.advance();
+        Ok(value)
+    }
+
+    fn at_literal(&self) -> bool {
+        match self.peek().kind {
+            TokenKind::Integer(_) | TokenKind::Real(_) | TokenKind::Minus | TokenKind::Str(_) | TokenKind::Blob(_) => true,
+            _ => self.is_keyword("null") || self.is_keyword("true") || self.is_keyword("false"),
+        }
+    }
+
+    /// A literal, or in the shorthand forms an unquoted word taken as text.
+    fn parse_bare_value(&mut self) -> Result<Literal, SyntaxError> {
+        if let TokenKind::Word(word) = &self.peek().kind {
+            let value = Literal::Text(word.clone());
+            self.advance();
+            return Ok(value);
+        }
+        self.parse_literal()
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, SyntaxError> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("or") {
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, SyntaxError> {
+        let mut left = self.parse_not()?;
+        while self.eat_keyword("and") {
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, SyntaxError> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, SyntaxError> {
+        let left = self.parse_operand()?;
+        if self.eat_keyword("is") {
+            let negated = self.eat_keyword("not");
+            self.expect_keyword("null")?;
+            let is_null = Expr::IsNull(Box::new(left));
+            if negated {
+                return Ok(Expr::Not(Box::new(is_null)));
+            }
+            return Ok(is_null);
+        }
+        let negated = self.is_keyword("not")
+            && matches!(&self.tokens[self.pos + 1].kind, TokenKind::Word(word) if word.eq_ignore_ascii_case("between"));
+        if negated {
+            self.advance();
+        }
+        if self.eat_keyword("between") {
+            let low = self.parse_operand()?;
+            self.expect_keyword("and")?;
+            let high = self.parse_operand()?;
+            let between = Expr::Between(Box::new(left), Box::new(low), Box::new(high));
+            if negated {
+                return Ok(Expr::Not(Box::new(between)));
+            }
+            return Ok(between);
+        }
+        let op = match self.peek().kind {
+            TokenKind::Equal => CompareOp::Equal,
+            TokenKind::NotEqual => CompareOp::NotEqual,
+            TokenKind::Less => CompareOp::Less,
+            TokenKind::LessEqual => CompareOp::LessEqual,
+            TokenKind::Greater => CompareOp::Greater,
+            TokenKind::GreaterEqual => CompareOp::GreaterEqual,
+            _ => return Ok(left),
+        };
+        self.advance();
+        let right = self.parse_operand()?;
+        Ok(Expr::Compare(op, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr, SyntaxError> {
+        match self.peek().kind {
+            TokenKind::LeftParen => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect(&TokenKind::RightParen)?;
+                Ok(expr)
+            }
+            _ if self.at_literal() => Ok(Expr::Literal(self.parse_literal()?)),
+            TokenKind::Word(_) => Ok(Expr::Column(self.parse_identifier("an expression")?)),
+            _ => self.error("an expression"),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Evaluation of `WHERE` predicates, and the range of primary keys a
+//! predicate can possibly match so that a scan can seek instead of reading
+//! every leaf, or the value an indexed column must equal.
+
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::ops::Bound::{Excluded, Included, Unbounded};
+
+use crate::ast::{AggregateFunction, CompareOp, Expr, Literal};
+
+/// Bounds on the primary key, as a `RangeBounds<usize>`.
+pub type KeyRange = (Bound<usize>, Bound<usize>);
+
+/// Rank of a value's type in the sort order: NULL, then numbers, then text,
+/// then blobs, as in SQLite.
+fn type_rank(value: &Literal) -> u8 {
+    match value {
+        Literal::Null => 0,
+        Literal::Integer(_) | Literal::Real(_) | Literal::Boolean(_) => 1,
+        Literal::Text(_) => 2,
+        Literal::Blob(_) => 3,
+    }
+}
+
+fn as_real(value: &Literal) -> f64 {
+    match value {
+        Literal::Integer(value) => *value as f64,
+        Literal::Real(value) => *value,
+        Literal::Boolean(value) => *value as i64 as f64,
+        _ => 0.0,
+    }
+}
+
+/// Order two values. Numbers of any type compare by value; a boolean counts
+/// as 0 or 1.
+pub fn compare_literals(left: &Literal, right: &Literal) -> Ordering {
+    match (left, right) {
+        (Literal::Integer(left), Literal::Integer(right)) => left.cmp(right),
+        (Literal::Text(left), Literal::Text(right)) => left.cmp(right),
+        (Literal::Blob(left), Literal::Blob(right)) => left.cmp(right),
+        _ if type_rank(left) == 1 && type_rank(right) == 1 => as_real(left).total_cmp(&as_real(right)),
+        _ => type_rank(left).cmp(&type_rank(right)),
+    }
+}
+
+/// Truth of a value, or `None` for NULL. Numbers are true unless zero; text
+/// and blobs are false.
+fn truth(value: &Literal) -> Option<bool> {
+    match value {
+        Literal::Null => None,
+        Literal::Integer(_) | Literal::Real(_) | Literal::Boolean(_) => Some(as_real(value) != 0.0),
+        Literal::Text(_) | Literal::Blob(_) => Some(false),
+    }
+}
+
+pub fn is_true(value: &Literal) -> bool {
+    truth(value) == Some(true)
+}
+
+fn from_truth(value: Option<bool>) -> Literal {
+    match value {
+        Some(value) => Literal::Boolean(value),
+        None => Literal::Null,
+    }
+}
+
+/// A comparison is unknown when either side is NULL.
+fn compare(op: CompareOp, left: &Literal, right: &Literal) -> Option<bool> {
+    if *left == Literal::Null || *right == Literal::Null {
+        return None;
+    }
+    let ordering = compare_literals(left, right);
+    Some(match op {
+        CompareOp::Equal => ordering == Ordering::Equal,
+        CompareOp::NotEqual => ordering != Ordering::Equal,
+        CompareOp::Less => ordering == Ordering::Less,
+        CompareOp::LessEqual => ordering != Ordering::Greater,
+        CompareOp::Greater => ordering == Ordering::Greater,
+        CompareOp::GreaterEqual => ordering != Ordering::Less,
+    })
+}
+
+fn and(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+fn or(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Evaluate an expression against one row, whose columns are looked up
+/// through `column`. Predicates evaluate to true, false or, when they depend
+/// on a NULL, NULL; a row matches a filter only when it is true.
+pub fn eval(expr: &Expr, column: &dyn Fn(&str) -> Literal) -> Literal {
+    match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Column(name) => column(name),
+        Expr::Compare(op, left, right) => from_truth(compare(*op, &eval(left, column), &eval(right, column))),
+        Expr::Between(value, low, high) => {
+            let value = eval(value, column);
+            from_truth(and(compare(CompareOp::GreaterEqual, &value, &eval(low, column)),
+                compare(CompareOp::LessEqual, &value, &eval(high, column))))
+        }
+        Expr::And(left, right) => from_truth(and(truth(&eval(left, column)), truth(&eval(right, column)))),
+        Expr::Or(left, right) => from_truth(or(truth(&eval(left, column)), truth(&eval(right, column)))),
+        Expr::Not(expr) => from_truth(truth(&eval(expr, column)).map(|value| !value)),
+        Expr::IsNull(expr) => Literal::Boolean(eval(expr, column) == Literal::Null),
+    }
+}
+
+/// Every column an expression refers to.
+pub fn expr_columns(expr: &Expr) -> Vec<&str> {
+    match expr {
+        Expr::Literal(_) => vec![],
+        Expr::Column(name) => vec![name.as_str()],
+        Expr::Compare(_, left, right) | Expr::And(left, right) | Expr::Or(left, right) => {
+            let mut columns = expr_columns(left);
+            columns.extend(expr_columns(right));
+            columns
+        }
+        Expr::Between(value, low, high) => {
+            let mut columns = expr_columns(value);
+            columns.extend(expr_columns(low));
+            columns.extend(expr_columns(high));
+            columns
+        }
+        Expr::Not(expr) | Expr::IsNull(expr) => expr_columns(expr),
+    }
+}
+
+/// The smallest key range outside which `filter` cannot hold, considering
+/// comparisons of `key_column` with integer literals joined by `AND`. Any
+/// other predicate leaves the range unbounded; the filter is still evaluated
+/// on every row inside the range.
+pub fn key_range(filter: Option<&Expr>, key_column: &str) -> KeyRange {
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return (Unbounded, Unbounded),
+    };
+    match filter {
+        Expr::And(left, right) => {
+            let (left_start, left_end) = key_range(Some(left), key_column);
+            let (right_start, right_end) = key_range(Some(right), key_column);
+            (max_start(left_start, right_start), min_end(left_end, right_end))
+        }
+        Expr::Compare(op, left, right) => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(column), Expr::Literal(Literal::Integer(value))) if column == key_column => {
+                compare_range(*op, *value)
+            }
+            (Expr::Literal(Literal::Integer(value)), Expr::Column(column)) if column == key_column => {
+                compare_range(flip(*op), *value)
+            }
+            _ => (Unbounded, Unbounded),
+        },
+        Expr::Between(value, low, high) => match (value.as_ref(), low.as_ref(), high.as_ref()) {
+            (Expr::Column(column), Expr::Literal(Literal::Integer(low)), Expr::Literal(Literal::Integer(high)))
+                if column == key_column => {
+                let (start, _) = compare_range(CompareOp::GreaterEqual, *low);
+                let (_, end) = compare_range(CompareOp::LessEqual, *high);
+                (start, end)
+            }
+            _ => (Unbounded, Unbounded),
+        },
+        _ => (Unbounded, Unbounded),
+    }
+}
+
+/// The value `column` must equal for `filter` to hold, taken from a
+/// comparison `column = literal` among the predicates joined by `AND`, so
+/// that an index on the column can list the rows to read.
+pub fn equality_value(filter: Option<&Expr>, column: &str) -> Option<Literal> {
+    match filter? {
+        Expr::And(left, right) => equality_value(Some(left), column).or_else(|| equality_value(Some(right), column)),
+        Expr::Compare(CompareOp::Equal, left, right) => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(name), Expr::Literal(value)) | (Expr::Literal(value), Expr::Column(name)) if name == column => {
+                Some(value.clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `key <op> value` with the operands swapped, so that `5 < id` reads `id > 5`.
+fn flip(op: CompareOp) -> CompareOp {
+    match op {
+        CompareOp::Less => CompareOp::Greater,
+        CompareOp::LessEqual => CompareOp::GreaterEqual,
+        CompareOp::Greater => CompareOp::Less,
+        CompareOp::GreaterEqual => CompareOp::LessEqual,
+        op => op,
+    }
+}
+
+/// Keys satisfying `key <op> value`. Keys are never negative, so a bound
+/// below zero either disappears or leaves the range empty.
+fn compare_range(op: CompareOp, value: i64) -> KeyRange {
+    /* An upper bound that no key satisfies */
+    const EMPTY: Bound<usize> = Excluded(0);
+    let key = value.max(0) as usize;
+    match op {
+        CompareOp::Equal if value < 0 => (Unbounded, EMPTY),
+        CompareOp::Equal => (Included(key), Included(key)),
+        CompareOp::NotEqual => (Unbounded, Unbounded),
+        CompareOp::Less if value <= 0 => (Unbounded, EMPTY),
+        CompareOp::Less => (Unbounded, Excluded(key)),
+        CompareOp::LessEqual if value < 0 => (Unbounded, EMPTY),
+        CompareOp::LessEqual => (Unbounded, Included(key)),
+        CompareOp::Greater if value < 0 => (Unbounded, Unbounded),
+        CompareOp::Greater => (Excluded(key), Unbounded),
+        CompareOp::GreaterEqual => (Included(key), Unbounded),
+    }
+}
+
+fn max_start(left: Bound<usize>, right: Bound<usize>) -> Bound<usize> {
+    match (left, right) {
+        (Unbounded, bound) | (bound, Unbounded) => bound,
+        (Included(l), Included(r)) => Included(l.max(r)),
+        (Excluded(l), Excluded(r)) => Excluded(l.max(r)),
+        (Included(i), Excluded(e)) | (Excluded(e), Included(i)) => if i > e { Included(i) } else { Excluded(e) },
+    }
+}
+
+fn min_end(left: Bound<usize>, right: Bound<usize>) -> Bound<usize> {
+    match (left, right) {
+        (Unbounded, bound) | (bound, Unbounded) => bound,
+        (Included(l), Included(r)) => Included(l.min(r)),
+        (Excluded(l), Excluded(r)) => Excluded(l.min(r)),
+        (Included(i), Excluded(e)) | (Excluded(e), Included(i)) => if i < e { Included(i) } else { Excluded(e) },
+    }
+}
+
+/// Whether a key lies before the start of the range, so that a scan in
+/// descending key order can stop.
+pub fn before_start(range: &KeyRange, key: usize) -> bool {
+    match range.0 {
+        Included(start) => key < start,
+        Excluded(start) => key <= start,
+        Unbounded => false,
+    }
+}
+
+/// Whether a key lies past the end of the range, so that a scan in key order
+/// can stop.
+pub fn past_end(range: &KeyRange, key: usize) -> bool {
+    match range.1 {
+        Included(end) => key > end,
+        Excluded(end) => key >= end,
+        Unbounded => false,
+    }
+}
+
+/// Running state of one aggregate over the rows of a scan.
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// Column aggregated; `None` for `count(*)`.
+    pub column: Option<String>,
+    /// Rows folded, or for an aggregate of a column its non-NULL values.
+    pub count: usize,
+    sum: i128,
+    /// Sum kept as a float once a REAL value has been added.
+    real_sum: Option<f64>,
+    pub min: Option<Literal>,
+    pub max: Option<Literal>,
+}
+
+impl Aggregate {
+    pub fn new(function: AggregateFunction, column: Option<String>) -> Self {
+        Aggregate {
+            function,
+            column,
+            count: 0,
+            sum: 0,
+            real_sum: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Whether the tree structure alone answers this aggregate over the
+    /// whole table: `count(*)` from the leaf cell counts, `min(key)` and
+    /// `max(key)` from the outermost leaves.
+    pub fn answered_by_tree(&self, key_column: &str) -> bool {
+        let on_key = self.column.as_deref() == Some(key_column);
+        match self.function {
+            AggregateFunction::Count => self.column.is_none() || on_key,
+            AggregateFunction::Min | AggregateFunction::Max => on_key,
+            AggregateFunction::Sum | AggregateFunction::Avg => false,
+        }
+    }
+
+    /// Fold one row, whose columns are looked up through `column`.
+    pub fn add(&mut self, column: &dyn Fn(&str) -> Literal) {
+        let value = match &self.column {
+            Some(name) => column(name),
+            None => {
+                self.count += 1;
+                return;
+            }
+        };
+        if value == Literal::Null {
+            return;
+        }
+        self.count += 1;
+        match value {
+            Literal::Integer(value) => self.sum += value as i128,
+            Literal::Real(value) => *self.real_sum.get_or_insert(0.0) += value,
+            _ => {}
+        }
+        if self.min.as_ref().is_none_or(|min| compare_literals(&value, min) == Ordering::Less) {
+            self.min = Some(value.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| compare_literals(&value, max) == Ordering::Greater) {
+            self.max = Some(value);
+        }
+    }
+
+    /// The column heading of the aggregate, such as `count(*)`.
+    pub fn name(&self) -> String {
+        let function = match self.function {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+        };
+        format!("{}({})", function, self.column.as_deref().unwrap_or("*"))
+    }
+
+    /// The result. Everything but `count` is NULL over no values, and a sum
+    /// of integers too large for an INTEGER is REAL.
+    pub fn result(&self) -> Literal {
+        if self.count == 0 && self.function != AggregateFunction::Count {
+            return Literal::Null;
+        }
+        match self.function {
+            AggregateFunction::Count => Literal::Integer(self.count as i64),
+            AggregateFunction::Min => self.min.clone().unwrap_or(Literal::Null),
+            AggregateFunction::Max => self.max.clone().unwrap_or(Literal::Null),
+            AggregateFunction::Sum => match (self.real_sum, i64::try_from(self.sum)) {
+                (None, Ok(sum)) => Literal::Integer(sum),
+                (real_sum, _) => Literal::Real(real_sum.unwrap_or(0.0) + self.sum as f64),
+            },
+            AggregateFunction::Avg => {
+                Literal::Real((self.real_sum.unwrap_or(0.0) + self.sum as f64) / self.count as f64)
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Write-ahead log.
+//!
+//! Modified pages are appended to `<database>-wal` instead of overwriting the
+//! database file. A transaction is durable once its commit frame, the last
+//! frame it appends, has been synced. Every frame carries a checksum chained
+//! from the one before it, so a torn or stale tail is detected and ignored
+//! when the log is recovered. Checkpointing copies the newest committed
+//! version of each page back into the database file and starts the log over.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{valid_page_size, Page};
+use crate::error::Result;
+
+/*
+ * WAL Header Layout. Integers are little-endian.
+ */
+const WAL_MAGIC: &[u8; WAL_MAGIC_SIZE] = b"dbsimwal";
+const WAL_FORMAT_VERSION: u32 = 1;
+const WAL_MAGIC_SIZE: usize = 8;
+const WAL_MAGIC_OFFSET: usize = 0;
+const WAL_FORMAT_VERSION_OFFSET: usize = WAL_MAGIC_OFFSET + WAL_MAGIC_SIZE;
+const WAL_PAGE_SIZE_OFFSET: usize = WAL_FORMAT_VERSION_OFFSET + 4;
+const WAL_SALT_OFFSET: usize = WAL_PAGE_SIZE_OFFSET + 4;
+const WAL_CHECKSUM_OFFSET: usize = WAL_SALT_OFFSET + 8;
+const WAL_HEADER_SIZE: usize = WAL_CHECKSUM_OFFSET + 8;
+
+/*
+ * WAL Frame Layout: a frame header followed by the page image.
+ * `db_size` is the database size in pages on commit frames and 0 otherwise.
+ */
+const FRAME_PAGE_NUM_OFFSET: usize = 0;
+const FRAME_DB_SIZE_OFFSET: usize = FRAME_PAGE_NUM_OFFSET + 8;
+const FRAME_SALT_OFFSET: usize = FRAME_DB_SIZE_OFFSET + 8;
+const FRAME_CHECKSUM_OFFSET: usize = FRAME_SALT_OFFSET + 8;
+const FRAME_HEADER_SIZE: usize = FRAME_CHECKSUM_OFFSET + 8;
+
+const CHECKSUM_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a over `data`, continuing from `seed`.
+fn checksum(seed: u64, data: &[u8]) -> u64 {
+    let mut hash = seed;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Read the header at the start of a log. Returns the page size it records,
+/// or None if the log has no valid header.
+fn read_header(file: &mut File) -> Option<usize> {
+    let mut header = [0u8; WAL_HEADER_SIZE];
+    if file.seek(SeekFrom::Start(0)).is_err() || file.read_exact(&mut header).is_err() {
+        return None;
+    }
+    let page_size = read_u32(&header, WAL_PAGE_SIZE_OFFSET) as usize;
+    if &header[WAL_MAGIC_OFFSET..WAL_MAGIC_OFFSET + WAL_MAGIC_SIZE] != WAL_MAGIC
+        || read_u32(&header, WAL_FORMAT_VERSION_OFFSET) != WAL_FORMAT_VERSION
+        || !valid_page_size(page_size)
+        || read_u64(&header, WAL_CHECKSUM_OFFSET) != checksum(CHECKSUM_SEED, &header[..WAL_CHECKSUM_OFFSET]) {
+        return None;
+    }
+    Some(page_size)
+}
+
+pub struct Wal {
+    file: File,
+    path: String,
+    page_size: usize,
+    salt: u64,
+    /// Checksum of the last frame appended, which seeds the next one.
+    checksum: u64,
+    length: u64,
+    /// Log length and checksum at the last commit. Frames past this point
+    /// belong to the transaction in progress.
+    commit_length: u64,
+    commit_checksum: u64,
+    /// Offset of the newest committed image of each logged page.
+    committed: HashMap<usize, u64>,
+    /// Offset of pages spilled or logged by the transaction in progress.
+    pending: HashMap<usize, u64>,
+}
+
+impl Wal {
+    /// Open the log next to `db_filename`, recovering every transaction that
+    /// committed before the last shutdown. A log of pages of another size
+    /// than `page_size` is discarded.
+    pub fn wal_open(db_filename: &str, page_size: usize) -> Result<Wal> {
+        let path = format!("{}-wal", db_filename);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .read(true)
+            .open(&path)?;
+
+        let mut wal = Wal {
+            file,
+            path,
+            page_size,
+            salt: 0,
+            checksum: 0,
+            length: 0,
+            commit_length: 0,
+            commit_checksum: 0,
+            committed: HashMap::new(),
+            pending: HashMap::new(),
+        };
+        if !wal.recover()? {
+            wal.reset()?;
+        }
+        Ok(wal)
+    }
+
+    pub fn wal_exists(db_filename: &str) -> bool {
+        Path::new(&format!("{}-wal", db_filename)).exists()
+    }
+
+    /// The page size recorded by the log next to `db_filename`, if there is
+    /// one with a valid header.
+    pub fn wal_page_size(db_filename: &str) -> Option<usize> {
+        let mut file = File::open(format!("{}-wal", db_filename)).ok()?;
+        read_header(&mut file)
+    }
+
+    fn frame_size(&self) -> usize {
+        FRAME_HEADER_SIZE + self.page_size
+    }
+
+    /// Scan the log and index every frame that belongs to a committed
+    /// transaction. Returns false if the log has no valid header.
+    fn recover(&mut self) -> Result<bool> {
+        if read_header(&mut self.file) != Some(self.page_size) {
+            return Ok(false);
+        }
+        let mut header = [0u8; WAL_HEADER_SIZE];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_exact(&mut header)?;
+        self.salt = read_u64(&header, WAL_SALT_OFFSET);
+        self.checksum = read_u64(&header, WAL_CHECKSUM_OFFSET);
+        self.commit_checksum = self.checksum;
+        self.length = WAL_HEADER_SIZE as u64;
+        self.commit_length = self.length;
+
+        let mut frame = vec![0u8; self.frame_size()];
+        let mut transaction = HashMap::new();
+        while self.file.read_exact(&mut frame).is_ok() {
+            let frame_checksum = checksum(self.checksum, &frame[..FRAME_CHECKSUM_OFFSET]);
+            let frame_checksum = checksum(frame_checksum, &frame[FRAME_HEADER_SIZE..]);
+            if read_u64(&frame, FRAME_SALT_OFFSET) != self.salt
+                || read_u64(&frame, FRAME_CHECKSUM_OFFSET) != frame_checksum {
+                break;
+            }
+            self.checksum = frame_checksum;
+
+            let page_num = read_u64(&frame, FRAME_PAGE_NUM_OFFSET) as usize;
+            transaction.insert(page_num, self.length + FRAME_HEADER_SIZE as u64);
+            self.length += self.frame_size() as u64;
+
+            if read_u64(&frame, FRAME_DB_SIZE_OFFSET) != 0 {
+                self.committed.extend(transaction.drain());
+                self.commit_length = self.length;
+                self.commit_checksum = self.checksum;
+            }
+        }
+
+        /* Drop frames of a transaction that never committed */
+        self.truncate(self.commit_length)?;
+        self.checksum = self.commit_checksum;
+        self.length = self.commit_length;
+        Ok(true)
+    }
+
+    /// Start an empty log with a new salt, so frames left over from the
+    /// previous generation can never validate.
+    pub fn reset(&mut self) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        self.salt = checksum(self.salt ^ now, &now.to_le_bytes());
+
+        let mut header = [0u8; WAL_HEADER_SIZE];
+        header[WAL_MAGIC_OFFSET..WAL_MAGIC_OFFSET + WAL_MAGIC_SIZE].copy_from_slice(WAL_MAGIC);
+        header[WAL_FORMAT_VERSION_OFFSET..WAL_FORMAT_VERSION_OFFSET + 4].copy_from_slice(&WAL_FORMAT_VERSION.to_le_bytes());
+        header[WAL_PAGE_SIZE_OFFSET..WAL_PAGE_SIZE_OFFSET + 4].copy_from_slice(&(self.page_size as u32).to_le_bytes());
+        header[WAL_SALT_OFFSET..WAL_SALT_OFFSET + 8].copy_from_slice(&self.salt.to_le_bytes());
+        self.checksum = checksum(CHECKSUM_SEED, &header[..WAL_CHECKSUM_OFFSET]);
+        header[WAL_CHECKSUM_OFFSET..WAL_CHECKSUM_OFFSET + 8].copy_from_slice(&self.checksum.to_le_bytes());
+
+        self.truncate(0)?;
+        self.write_at(0, &header)?;
+        self.sync()?;
+
+        self.length = WAL_HEADER_SIZE as u64;
+        self.commit_length = self.length;
+        self.commit_checksum = self.checksum;
+        self.committed.clear();
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Return the newest image of a page in the log, if it has one.
+    pub fn read_page(&mut self, page_num: usize) -> Result<Option<Page>> {
+        let offset = match self.pending.get(&page_num).or_else(|| self.committed.get(&page_num)) {
+            Some(&offset) => offset,
+            None => return Ok(None),
+        };
+        let mut page = Page::new(self.page_size);
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut page.0)?;
+        Ok(Some(page))
+    }
+
+    /// Append a page image to the log. A non-zero `db_size` marks the frame
+    /// as the commit frame of the current transaction.
+    pub fn append_frame(&mut self, page_num: usize, page: &Page, db_size: usize) -> Result<()> {
+        let mut frame = vec![0u8; self.frame_size()];
+        frame[FRAME_PAGE_NUM_OFFSET..FRAME_PAGE_NUM_OFFSET + 8].copy_from_slice(&(page_num as u64).to_le_bytes());
+        frame[FRAME_DB_SIZE_OFFSET..FRAME_DB_SIZE_OFFSET + 8].copy_from_slice(&(db_size as u64).to_le_bytes());
+        frame[FRAME_SALT_OFFSET..FRAME_SALT_OFFSET + 8].copy_from_slice(&self.salt.to_le_bytes());
+        frame[FRAME_HEADER_SIZE..].copy_from_slice(&page.0);
+        let frame_checksum = checksum(checksum(self.checksum, &frame[..FRAME_CHECKSUM_OFFSET]), &frame[FRAME_HEADER_SIZE..]);
+        frame[FRAME_CHECKSUM_OFFSET..FRAME_CHECKSUM_OFFSET + 8].copy_from_slice(&frame_checksum.to_le_bytes());
+
+        self.write_at(self.length, &frame)?;
+        self.checksum = frame_checksum;
+        self.pending.insert(page_num, self.length + FRAME_HEADER_SIZE as u64);
+        self.length += self.frame_size() as u64;
+        Ok(())
+    }
+
+    /// Sync the log after the commit frame has been appended. Once this
+    /// returns the transaction survives a crash.
+    pub fn commit(&mut self) -> Result<()> {
+        self.sync()?;
+        self.committed.extend(self.pending.drain());
+        self.commit_length = self.length;
+        self.commit_checksum = self.checksum;
+        Ok(())
+    }
+
+    /// Discard the frames of the transaction in progress.
+    pub fn rollback(&mut self) -> Result<()> {
+        self.length = self.commit_length;
+        self.checksum = self.commit_checksum;
+        self.pending.clear();
+        self.truncate(self.commit_length)
+    }
+
+    pub fn is_pending(&self, page_num: usize) -> bool {
+        self.pending.contains_key(&page_num)
+    }
+
+    pub fn has_pending_frames(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn num_committed_frames(&self) -> usize {
+        (self.commit_length as usize - WAL_HEADER_SIZE) / self.frame_size()
+    }
+
+    /// Page numbers with a committed image in the log, in ascending order.
+    pub fn committed_pages(&self) -> Vec<usize> {
+        let mut page_nums: Vec<usize> = self.committed.keys().copied().collect();
+        page_nums.sort_unstable();
+        page_nums
+    }
+
+    /// Delete the log file. Only valid right after a checkpoint.
+    pub fn wal_close(&mut self) -> Result<()> {
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(bytes)?;
+        Ok(())
+    }
+
+    fn truncate(&mut self, length: u64) -> Result<()> {
+        self.file.set_len(length)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
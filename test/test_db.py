@@ -14,6 +14,10 @@ def run(commands, cmd_args):
     # print("out: ", out)
     return out
 
+def wide_row(i):
+    # Rows as wide as the users table allows, so that 13 fit in a leaf
+    return f'user{i}'.ljust(32, '_'), f'person{i}@example.com'.ljust(255, 'x')
+
 def test_insert():
     global db_file
     try:
@@ -224,10 +228,13 @@ def test_split_internal():
     global db_file
     try :
         commands = []
-        for i in range(1, 100):
-            commands.append(f"insert {i} user{i} person{i}@example.com")
+        # Filled in key order, leaves keep 7 wide rows; an internal node
+        # takes a little over 200 children before it splits.
+        for i in range(1, 1501):
+            commands.append("insert {} {} {}".format(i, *wide_row(i)))
         commands.append("select")
         commands.append(".btree")
+        commands.append(".stats users")
         commands.append(".exit")
         # print(commands)
         db_file = root_path + 'split_internal.db'
@@ -236,345 +243,1309 @@ def test_split_internal():
         cmd_args.append(db_file)
         out = run(commands, cmd_args)
         # print(out)
+        tree = out.split('db > ')[-3]
+        assert out.replace(tree, '') == 'db > Executed.\n' * 1500 + 'db > ' + ''.join(
+            '{} "{}" "{}"\n'.format(i, *wide_row(i)) for i in range(1, 1501)) + '''Executed.
+db > db > Depth: 3
+db > '''
+        lines = tree.split('\n')
+        assert [line for line in lines if 'internal' in line or line.startswith(' - key')] == \
+            ['- internal (size 1)', ' - internal (size 102)', ' - key 721', ' - internal (size 110)']
+        assert sum(1 for line in lines if 'leaf' in line) == 214
+        assert '  - leaf (size 7)\n   715\n   716\n   717\n   718\n   719\n   720\n   721\n - key 721\n - internal (size 110)\n  - leaf (size 7)\n   722\n' in tree
+    finally:
+        os.remove(db_file)
+
+def test_buffer_pool_eviction():
+    global db_file
+    try:
+        commands = []
+        for i in range(1, 1501):
+            commands.append(f"insert {i} user{i} person{i}@example.com")
+        commands.append(".exit")
+        db_file = root_path + 'buffer_pool.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        cmd_args.append('--cache-frames')
+        cmd_args.append('8')
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 1500 + 'db > '
+
+        commands = ['select',
+                    '.exit']
+        out = run(commands, cmd_args)
+        expected = ''.join(f'{i} "user{i}" "person{i}@example.com"\n' for i in range(1, 1501))
+        assert out == 'db > ' + expected + 'Executed.\ndb > '
+    finally:
+        os.remove(db_file)
+
+def test_delete():
+    global db_file
+    try:
+        commands = ['insert 1 user1 person1@example.com',
+                    'insert 2 user2 person2@example.com',
+                    'insert 3 user3 person3@example.com',
+                    'delete 2',
+                    'delete 2',
+                    'select',
+                    '.exit']
+        db_file = root_path + 'delete.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
         assert out == '''db > Executed.
 db > Executed.
 db > Executed.
 db > Executed.
+db > Error: Key not found.
+db > 1 "user1" "person1@example.com"
+3 "user3" "person3@example.com"
+Executed.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_delete_merge():
+    global db_file
+    try:
+        commands = []
+        for i in range(1, 31):
+            commands.append("insert {} {} {}".format(i, *wide_row(i)))
+        for i in range(1, 21):
+            commands.append(f"delete {i}")
+        commands.append(".exit")
+        db_file = root_path + 'delete_merge.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 50 + 'db > '
+
+        commands = ['.btree',
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == '''db > Tree: 
+- leaf (size 10)
+ 21
+ 22
+ 23
+ 24
+ 25
+ 26
+ 27
+ 28
+ 29
+ 30
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_update():
+    global db_file
+    try:
+        commands = ['insert 1 user1 person1@example.com',
+                    'insert 2 user2 person2@example.com',
+                    'update 1 set username=alice email=alice@example.com',
+                    'update 2 set email=bob@example.com',
+                    'update 3 set username=carol',
+                    'select',
+                    '.exit']
+        db_file = root_path + 'update.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''db > Executed.
 db > Executed.
 db > Executed.
 db > Executed.
+db > Error: Key not found.
+db > 1 "alice" "alice@example.com"
+2 "user2" "bob@example.com"
+Executed.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_free_list():
+    global db_file
+    try:
+        commands = []
+        for i in range(1, 31):
+            commands.append("insert {} {} {}".format(i, *wide_row(i)))
+        commands.append(".stats")
+        for i in range(1, 21):
+            commands.append(f"delete {i}")
+        commands.append(".stats")
+        commands.append(".exit")
+        db_file = root_path + 'free_list.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 30 + '''db > Pages: 7
+Free pages: 0
+''' + 'db > Executed.\n' * 20 + '''db > Pages: 7
+Free pages: 4
+db > '''
+
+        commands = []
+        for i in range(31, 46):
+            commands.append("insert {} {} {}".format(i, *wide_row(i)))
+        commands.append(".stats")
+        commands.append(".exit")
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 15 + '''db > Pages: 7
+Free pages: 1
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_foreign_file():
+    global db_file
+    try:
+        db_file = root_path + 'foreign.db'
+        with open(db_file, 'wb') as f:
+            f.write(b'not a database'.ljust(4096, b'\0'))
+        commands = ['select',
+                    '.exit']
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''File is not a db_simple database.
+'''
+    finally:
+        os.remove(db_file)
+
+def test_corrupt_page():
+    global db_file
+    try:
+        db_file = root_path + 'corrupt_page.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        run(['insert 1 user1 person1@example.com', '.exit'], cmd_args)
+        with open(db_file, 'rb') as f:
+            image = f.read()
+
+        # Page 2 is the root leaf of users: an unknown node type byte, then a
+        # cell offset past the end of the page. The error is reported and the
+        # session goes on.
+        with open(db_file, 'wb') as f:
+            f.write(image[:8192] + b'\x07' + image[8193:])
+        out = run(['select', 'insert 2 user2 person2@example.com', '.tables', '.exit'], cmd_args)
+        assert out == """db > Page 2: unknown node type 7. Corrupt file.
+db > Page 2: unknown node type 7. Corrupt file.
+db > users
+db > """
+
+        with open(db_file, 'wb') as f:
+            f.write(image[:8226] + (4095).to_bytes(2, 'little') + image[8228:])
+        out = run(['select', '.exit'], cmd_args)
+        assert out == """db > Page 2: cell 0 at offset 4095 outside the cell area. Corrupt file.
+db > """
+
+        # Page 3 is the root leaf of an index, whose first cell starts with
+        # the length of its key. A key longer than a leaf cell is reported.
+        os.remove(db_file)
+        run(['insert 1 user1 person1@example.com', 'create index byemail on users(email)', '.exit'], cmd_args)
+        with open(db_file, 'rb') as f:
+            image = f.read()
+        offset = 12288 + int.from_bytes(image[12322:12324], 'little')
+        with open(db_file, 'wb') as f:
+            f.write(image[:offset] + (4000).to_bytes(2, 'little') + image[offset + 2:])
+        out = run(["select id from users where email = 'person1@example.com'", '.exit'], cmd_args)
+        assert out == """db > Page 3: key of cell 0 is too long for a leaf cell. Corrupt file.
+db > """
+
+        # A leaf that links to itself as the next leaf, here the catalog root
+        # on page 1 read at open, and a root whose right child is itself.
+        os.remove(db_file)
+        run([f"insert {i} user{i} {f'person{i}@example.com':x<255}" for i in range(1, 41)] + ['.exit'], cmd_args)
+        with open(db_file, 'rb') as f:
+            image = f.read()
+        with open(db_file, 'wb') as f:
+            f.write(image[:4114] + (1).to_bytes(8, 'little') + image[4122:])
+        out = run(['select', '.exit'], cmd_args)
+        assert out == "Page 1: leaf chain longer than the 8 pages of the file. Corrupt file.\n"
+
+        with open(db_file, 'wb') as f:
+            f.write(image[:8210] + (2).to_bytes(8, 'little') + image[8218:])
+        out = run(['select id from users where id = 40', 'select max(id) from users', '.exit'], cmd_args)
+        assert out == """db > Page 2: tree deeper than 64 levels. Corrupt file.
+db > Page 2: tree deeper than 64 levels. Corrupt file.
+db > """
+
+        # A right child past the end of the file is reported rather than read
+        # as a page of zeros.
+        with open(db_file, 'wb') as f:
+            f.write(image[:8210] + (100).to_bytes(8, 'little') + image[8218:])
+        out = run(['select max(id) from users', '.btree', '.exit'], cmd_args)
+        assert out == """db > Page 100 is past the end of the file. Corrupt file.
+db > Page 100 is past the end of the file. Corrupt file.
+db > """
+
+        # A 10000-byte value of notes, whose root leaf is page 3, goes on in
+        # overflow pages 6, 5 and 4. A chain that loops back on itself or
+        # leaves the file is reported, and none of its pages are freed.
+        os.remove(db_file)
+        body = ''.join(chr(ord('a') + i % 26) for i in range(10000))
+        run(['create table notes (id integer primary key, body text)',
+             f"insert into notes values (1, '{body}')",
+             '.exit'], cmd_args)
+        with open(db_file, 'rb') as f:
+            image = f.read()
+        for offset, next_page_num, error in ((20480, 5, 'Page 3: overflow chain loops back on itself'),
+                                             (24576, 100, 'Page 3: overflow page 100 out of range')):
+            with open(db_file, 'wb') as f:
+                f.write(image[:offset] + next_page_num.to_bytes(8, 'little') + image[offset + 8:])
+            out = run(['delete from notes where id = 1',
+                       'update notes set body = null where id = 1',
+                       '.stats',
+                       '.exit'], cmd_args)
+            assert out == f"""db > {error}. Corrupt file.
+db > {error}. Corrupt file.
+db > Pages: 7
+Free pages: 0
+db > """
+
+        # Deleting rows puts pages 3, 5, 6 and 4 on the free list: page 3 is
+        # its trunk and holds the other three. A 34th row splits a leaf and
+        # takes the last of them, unless the trunk's count, that page number
+        # or the head of the list in the header is out of range.
+        os.remove(db_file)
+        run(["insert {} {} {}".format(i, *wide_row(i)) for i in range(1, 31)] + [f"delete {i}" for i in range(1, 21)] + ['.exit'], cmd_args)
+        with open(db_file, 'rb') as f:
+            image = f.read()
+        for offset, value, error in ((12296, 100000, 'Page 3: 100000 free pages do not fit in a free-list trunk'),
+                                     (12320, 0, 'Free page 0 is out of range'),
+                                     (12320, 7, 'Free page 7 is out of range'),
+                                     (40, 9, 'Free page 9 is out of range')):
+            with open(db_file, 'wb') as f:
+                f.write(image[:offset] + value.to_bytes(8, 'little') + image[offset + 8:])
+            out = run(["insert {} {} {}".format(i, *wide_row(i)) for i in range(31, 35)] + ['.exit'], cmd_args)
+            assert out == 'db > Executed.\n' * 3 + f'db > {error}. Corrupt file.\ndb > '
+
+        # Counting the free pages walks the trunks, which must stay in range
+        # and not loop back on themselves.
+        for offset, value, error in ((12288, 3, 'Free list holds more than the 7 pages of the file'),
+                                     (12288, 7, 'Free page 7 is out of range'),
+                                     (40, 9, 'Free page 9 is out of range')):
+            with open(db_file, 'wb') as f:
+                f.write(image[:offset] + value.to_bytes(8, 'little') + image[offset + 8:])
+            out = run(['.stats', '.exit'], cmd_args)
+            assert out == f'db > {error}. Corrupt file.\ndb > '
+    finally:
+        os.remove(db_file)
+
+def test_crash_recovery():
+    global db_file
+    try:
+        db_file = root_path + 'crash_recovery.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        pipes = Popen(cmd_args, stdin=PIPE, stdout=PIPE)
+        for i in range(1, 4):
+            pipes.stdin.write(f"insert {i} user{i} person{i}@example.com\n".encode('utf-8'))
+        pipes.stdin.flush()
+        out = b''
+        while out.count(b'Executed.') < 3:
+            out += os.read(pipes.stdout.fileno(), 4096)
+        # Simulate a crash: no .exit, so nothing is checkpointed
+        pipes.kill()
+        pipes.wait()
+        assert os.path.exists(db_file + '-wal')
+
+        # A torn frame at the tail of the log must be ignored
+        with open(db_file + '-wal', 'ab') as f:
+            f.write(b'\xff' * 1000)
+
+        commands = ['select',
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == '''db > 1 "user1" "person1@example.com"
+2 "user2" "person2@example.com"
+3 "user3" "person3@example.com"
+Executed.
+db > '''
+        assert not os.path.exists(db_file + '-wal')
+    finally:
+        os.remove(db_file)
+
+def test_transaction():
+    global db_file
+    try:
+        commands = ['insert 1 user1 person1@example.com',
+                    'begin']
+        # Enough rows to split the root and spill pages out of a 4-frame cache
+        for i in range(2, 41):
+            commands.append(f"insert {i} user{i} person{i}@example.com")
+        commands += ['delete 1',
+                     'rollback',
+                     'select',
+                     '.stats',
+                     'begin',
+                     'update 1 set username=alice',
+                     'insert 2 user2 person2@example.com',
+                     'begin',
+                     'commit',
+                     'commit',
+                     'begin',
+                     'insert 3 user3 person3@example.com',
+                     '.exit']
+        db_file = root_path + 'transaction.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        cmd_args.append('--cache-frames')
+        cmd_args.append('4')
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 43 + '''db > 1 "user1" "person1@example.com"
+Executed.
+db > Pages: 3
+Free pages: 0
 db > Executed.
 db > Executed.
 db > Executed.
+db > Error: A transaction is already open.
 db > Executed.
+db > Error: No transaction is open.
 db > Executed.
 db > Executed.
+db > '''
+
+        # The transaction left open at .exit was rolled back
+        commands = ['select',
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == '''db > 1 "alice" "person1@example.com"
+2 "user2" "person2@example.com"
+Executed.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_sql_statements():
+    global db_file
+    try:
+        commands = ["INSERT INTO users VALUES (1, 'Ada Lovelace', 'ada@example.com');",
+                    "insert into users (email, id, username) values ('bob@example.com', 2, 'bob')",
+                    "Update users SET username = 'O''Brien' WHERE id = 2",
+                    "DELETE FROM users WHERE id = 1",
+                    "select * from users",
+                    "select * from people",
+                    "insert into users values (3, 'carol' 'carol@example.com')",
+                    "selct * from users",
+                    "insert into users values (4, 'dave')",
+                    "insert 'x' user4 person4@example.com",
+                    "insert -1 user4 person4@example.com",
+                    "insert 5 bob bob+tag@example.com",
+                    "insert 6 o'brien ob@example.com",
+                    "update 5 set username=o'neil",
+                    "select * from users where id >= 5",
+                    '.exit']
+        db_file = root_path + 'sql_statements.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''db > Executed.
 db > Executed.
 db > Executed.
 db > Executed.
+db > 2 "O'Brien" "bob@example.com"
+Executed.
+db > Error: No such table: people.
+db > Syntax error at position 38: expected ')', found string 'carol@example.com'.
+db > Syntax error at position 1: expected a statement, found 'selct'.
+db > Error: Expected 3 values, found 2.
+db > Error: Column id expects INTEGER.
+db > ID must be positive.
 db > Executed.
 db > Executed.
 db > Executed.
+db > 5 "o'neil" "bob+tag@example.com"
+6 "o'brien" "ob@example.com"
+Executed.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_select_where():
+    global db_file
+    try:
+        commands = []
+        for i in range(1, 51):
+            commands.append(f"insert {i} user{i % 3} person{i}@example.com")
+        commands += ['select * from users where id = 17',
+                     'select * from users where id between 20 and 22',
+                     'select * from users where id > 47',
+                     'select * from users where 4 >= id',
+                     "select * from users where id < 10 and username = 'user0'",
+                     "select * from users where email = 'person33@example.com' or id = 1",
+                     'select * from users where id = 99',
+                     'select * from users where age = 3',
+                     '.exit']
+        db_file = root_path + 'select_where.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 50 + '''db > 17 "user2" "person17@example.com"
+Executed.
+db > 20 "user2" "person20@example.com"
+21 "user0" "person21@example.com"
+22 "user1" "person22@example.com"
+Executed.
+db > 48 "user0" "person48@example.com"
+49 "user1" "person49@example.com"
+50 "user2" "person50@example.com"
+Executed.
+db > 1 "user1" "person1@example.com"
+2 "user2" "person2@example.com"
+3 "user0" "person3@example.com"
+4 "user1" "person4@example.com"
+Executed.
+db > 3 "user0" "person3@example.com"
+6 "user0" "person6@example.com"
+9 "user0" "person9@example.com"
+Executed.
+db > 1 "user1" "person1@example.com"
+33 "user0" "person33@example.com"
+Executed.
 db > Executed.
+db > Error: No such column: age.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_select_order_limit():
+    global db_file
+    try:
+        commands = []
+        for i in range(1, 31):
+            commands.append(f"insert {i} user{i % 4} person{i}@example.com")
+        commands += ['select id, username from users order by id desc limit 3',
+                     'select email from users where id > 10 order by id limit 2 offset 5',
+                     'select username, id from users where id <= 8 order by username desc, id',
+                     'select * from users order by id limit 0',
+                     'select order by id desc limit 1',
+                     'select name from users',
+                     'select * from users limit -1',
+                     '.exit']
+        db_file = root_path + 'select_order_limit.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 30 + '''db > 30 "user2"
+29 "user1"
+28 "user0"
+Executed.
+db > "person16@example.com"
+"person17@example.com"
+Executed.
+db > "user3" 3
+"user3" 7
+"user2" 2
+"user2" 6
+"user1" 1
+"user1" 5
+"user0" 4
+"user0" 8
+Executed.
 db > Executed.
+db > 30 "user2" "person30@example.com"
+Executed.
+db > Error: No such column: name.
+db > Syntax error at position 27: expected a non-negative integer, found '-'.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_aggregates():
+    global db_file
+    try:
+        commands = ['select count(*), min(id), max(id), sum(id), avg(id) from users']
+        for i in range(1, 41):
+            commands.append(f"insert {i * 3} user{i % 4} person{i}@example.com")
+        commands += ['select count(*), min(id), max(id) from users',
+                     'select count(*), sum(id), avg(id), min(username) from users where id > 60',
+                     "select count(email) from users where username = 'user1'",
+                     'select sum(email) from users',
+                     'select id, count(*) from users',
+                     '.exit']
+        db_file = root_path + 'aggregates.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''db > 0 NULL NULL NULL NULL
+Executed.
+''' + 'db > Executed.\n' * 40 + '''db > 40 3 120
+Executed.
+db > 20 1830 91.5 "user0"
+Executed.
+db > 10
+Executed.
+db > Error: Column email expects INTEGER or REAL.
+db > Error: Aggregates cannot be selected together with plain columns.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_create_table():
+    global db_file
+    try:
+        commands = ['create table books (isbn integer primary key, title text, author varchar(8), year int)',
+                    'create table books (id integer primary key)',
+                    'create table shelves (id integer, name text)',
+                    'create table shelves (id integer primary key, name text, name text)',
+                    'create table shelves (id integer primary key, width decimal)']
+        for i in range(1, 31):
+            commands.append(f"insert into books values ({i}, 'Book {i}', 'author{i % 3}', {1950 + i})")
+        commands += ["insert into books (isbn, title) values (100, 'Untitled')",
+                     "insert into books values (101, 'Dune', 'Frank Herbert', 1965)",
+                     "insert into books values (102, 'Dune', 'frank', 'soon')",
+                     'update books set year = 2000 where isbn = 3',
+                     'delete from books where isbn = 4',
+                     'select title, year from books where isbn < 6 or isbn = 100',
+                     'select count(*), sum(year) from books where author = \'author1\'',
+                     'insert 1 user1 person1@example.com',
+                     '.tables',
+                     '.exit']
+        db_file = root_path + 'create_table.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''db > Executed.
+db > Error: Table books already exists.
+db > Error: A table needs exactly one INTEGER PRIMARY KEY column.
+db > Error: Duplicate column name: name.
+db > Error: Unknown column type: decimal.
+''' + 'db > Executed.\n' * 31 + '''db > String is too long.
+db > Error: Column year expects INTEGER.
+db > Executed.
+db > Executed.
+db > "Book 1" 1951
+"Book 2" 1952
+"Book 3" 2000
+"Book 5" 1955
+"Untitled" NULL
+Executed.
+db > 9 17691
+Executed.
 db > Executed.
+db > users
+books
+db > '''
+
+        # Both tables and their schemas survive a reopen; a table created in a
+        # rolled back transaction does not
+        commands = ['begin',
+                    'create table shelves (id integer primary key, name text)',
+                    "insert into shelves values (1, 'attic')",
+                    'rollback',
+                    'select * from shelves',
+                    'select * from books where isbn > 29',
+                    'select',
+                    '.btree books',
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == '''db > Executed.
 db > Executed.
 db > Executed.
 db > Executed.
+db > Error: No such table: shelves.
+db > 30 "Book 30" "author0" 1980
+100 "Untitled" NULL NULL
+Executed.
+db > 1 "user1" "person1@example.com"
+Executed.
+db > Tree: 
+- leaf (size 30)
+ 1
+ 2
+ 3
+ 5
+ 6
+ 7
+ 8
+ 9
+ 10
+ 11
+ 12
+ 13
+ 14
+ 15
+ 16
+ 17
+ 18
+ 19
+ 20
+ 21
+ 22
+ 23
+ 24
+ 25
+ 26
+ 27
+ 28
+ 29
+ 30
+ 100
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_typed_columns():
+    global db_file
+    try:
+        commands = ['create table items (id integer primary key, name text, price real, data blob, active boolean, qty integer)',
+                    "insert into items values (1, 'pen', 1.5, x'00ff10', true, 3)",
+                    "insert into items values (2, 'ink', 12, x'', false, 300000)",
+                    "insert into items (id, name) values (3, 'pad')",
+                    "insert into items values (4, 'cap', -0.25, x'AB', 1, -5000000000)",
+                    "insert into items values (5, 'x', 'cheap', null, null, null)",
+                    "insert into items values (5, 'x', 1.0, 'ab', null, null)",
+                    "insert into items values (5, 'x', 1.0, null, 2, null)",
+                    "insert into items values (5, 'x', 1.0, null, true, 1.5)",
+                    "insert into items values (null, 'x', 1.0, null, true, 1)",
+                    "insert into items values (5, 'x', 1.0, x'abc', true, 1)",
+                    'select * from items',
+                    'select name from items where price is null',
+                    'select name from items where price is not null and active',
+                    'select name from items where not active',
+                    'select name from items order by price',
+                    'select count(*), count(price), sum(price), avg(price), min(price), max(price), sum(qty), min(data) from items',
+                    'select sum(name) from items',
+                    "select name from items where data = x'ab'",
+                    'update items set price = null where id = 1',
+                    '.exit']
+        db_file = root_path + 'typed_columns.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''db > Executed.
 db > Executed.
 db > Executed.
 db > Executed.
 db > Executed.
+db > Error: Column price expects REAL.
+db > Error: Column data expects BLOB.
+db > Error: Column active expects BOOLEAN.
+db > Error: Column qty expects INTEGER.
+db > Error: Column id expects INTEGER.
+db > Syntax error at position 40: malformed blob x'abc'.
+db > 1 "pen" 1.5 x'00FF10' true 3
+2 "ink" 12.0 x'' false 300000
+3 "pad" NULL NULL NULL NULL
+4 "cap" -0.25 x'AB' true -5000000000
+Executed.
+db > "pad"
+Executed.
+db > "pen"
+"cap"
+Executed.
+db > "ink"
+Executed.
+db > "pad"
+"cap"
+"pen"
+"ink"
+Executed.
+db > 4 3 13.25 4.416666666666667 -0.25 12.0 -4999699997 x''
+Executed.
+db > Error: Column name expects INTEGER or REAL.
+db > "cap"
+Executed.
 db > Executed.
+db > '''
+
+        # Values keep their types across a reopen
+        commands = ['select * from items where id <= 2', '.exit']
+        out = run(commands, cmd_args)
+        assert out == '''db > 1 "pen" NULL x'00FF10' true 3
+2 "ink" 12.0 x'' false 300000
+Executed.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_slotted_leaf():
+    global db_file
+    try:
+        # Short rows take only the space they need, so 80 share one leaf
+        commands = []
+        for i in range(1, 81):
+            commands.append(f"insert {i} user{i} person{i}@example.com")
+        commands += ['.stats', '.exit']
+        db_file = root_path + 'slotted_leaf.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 80 + '''db > Pages: 3
+Free pages: 0
+db > '''
+        os.remove(db_file)
+
+        # A leaf full of wide rows has no room left for a 14th. Deleting one
+        # leaves a hole that the next insert reclaims by compacting the leaf
+        # rather than splitting it.
+        commands = []
+        for i in range(1, 14):
+            commands.append("insert {} {} {}".format(i, *wide_row(i)))
+        commands += ['delete 5',
+                     "insert 14 {} {}".format(*wide_row(14)),
+                     '.stats',
+                     "insert 15 {} {}".format(*wide_row(15)),
+                     '.stats',
+                     'select id from users where id between 4 and 6 or id = 14',
+                     '.exit']
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 15 + '''db > Pages: 3
+Free pages: 0
+db > Executed.
+db > Pages: 5
+Free pages: 0
+db > 4
+6
+14
+Executed.
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_overflow_pages():
+    global db_file
+    try:
+        # A 10000-byte value keeps its first bytes in the leaf cell and the
+        # rest in a chain of 3 overflow pages, freed again when the row
+        # shrinks or goes away
+        long_body = ''.join(chr(ord('a') + i % 26) for i in range(10000))
+        blob = 'ab' * 5000
+        commands = ['create table notes (id integer primary key, body text, data blob)',
+                    f"insert into notes values (1, '{long_body}', null)",
+                    '.stats',
+                    "insert into notes values (2, 'short', null)",
+                    'select * from notes',
+                    "update notes set body = 'now short' where id = 1",
+                    '.stats',
+                    f"insert into notes values (3, null, x'{blob}')",
+                    '.stats',
+                    '.exit']
+        db_file = root_path + 'overflow_pages.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == '''db > Executed.
 db > Executed.
+db > Pages: 7
+Free pages: 0
 db > Executed.
+db > 1 "''' + long_body + '''" NULL
+2 "short" NULL
+Executed.
 db > Executed.
+db > Pages: 7
+Free pages: 3
 db > Executed.
+db > Pages: 7
+Free pages: 2
+db > '''
+
+        commands = ['select * from notes where id = 3',
+                    'delete from notes where id = 3',
+                    '.stats',
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == '''db > 3 NULL x''' + "'" + blob.upper() + "'" + '''
+Executed.
 db > Executed.
+db > Pages: 7
+Free pages: 3
+db > '''
+    finally:
+        os.remove(db_file)
+
+def test_migrate_native_format():
+    global db_file
+    try:
+        # A file of format version 5 stored node integers as native usize
+        # values, which on a 64-bit little-endian machine are the bytes of the
+        # current layout. Marking a fresh file as version 5 makes it migrate
+        # on open, keeping its rows and free pages.
+        rows = [(i, *wide_row(i)) for i in range(1, 41)]
+        commands = [f"insert {i} {username} {email}" for i, username, email in rows]
+        commands += [f"delete {i}" for i in range(1, 31)]
+        commands += ['.stats', '.exit']
+        db_file = root_path + 'migrate_native_format.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        before = run(commands, cmd_args)
+        assert before.endswith('db > Pages: 8\nFree pages: 5\ndb > ')
+        with open(db_file, 'rb') as f:
+            image = f.read()
+        assert int.from_bytes(image[16:20], 'little') == 7
+        image = image[:16] + (5).to_bytes(4, 'little') + image[20:]
+
+        # Counts and offsets read from the old pages are checked before they
+        # are used: the cells of the root leaf of users on page 2, the offset
+        # of its first cell, and the free pages of the first free-list trunk,
+        # which also holds one past the page count when it points to itself.
+        head = int.from_bytes(image[40:48], 'little')
+        for offset, value, size, error in ((8202, 100000, 8, 'Page 2: 100000 cells do not fit in a leaf'),
+                                           (8226, 4095, 2, 'Page 2: cell 0 at offset 4095 outside the cell area'),
+                                           (head * 4096 + 8, 100000, 8, f'Page {head}: 100000 free pages do not fit in a free-list trunk'),
+                                           (head * 4096, head, 8, 'Free list holds more than the 8 pages of the file')):
+            with open(db_file, 'wb') as f:
+                f.write(image[:offset] + value.to_bytes(size, 'little') + image[offset + size:])
+            out = run(['select', '.exit'], cmd_args)
+            assert out == error + '. Corrupt file.\n', out
+            if os.path.exists(db_file + '-wal'):
+                os.remove(db_file + '-wal')
+
+        with open(db_file, 'wb') as f:
+            f.write(image)
+
+        commands = ['select', '.stats', '.exit']
+        out = run(commands, cmd_args)
+        assert out == 'db > ' + ''.join(f'{i} "{username}" "{email}"\n' for i, username, email in rows[30:]) + \
+            'Executed.\ndb > Pages: 8\nFree pages: 5\ndb > '
+        with open(db_file, 'rb') as f:
+            f.seek(16)
+            assert int.from_bytes(f.read(4), 'little') == 7
+    finally:
+        os.remove(db_file)
+
+def test_migrate_integer_keys_format():
+    global db_file
+    try:
+        # A file of format version 6 kept its leaves in the current layout,
+        # but stored an internal node as at most three fixed cells of a child
+        # and an 8-byte key, and keyed an index by a hash of the value. Such
+        # a file is built here from a fresh one: the leaves of every tree get
+        # new internal levels of the old layout, and index leaves integer
+        # keys. Opening it rebuilds the trees and refills the indexes.
+        rows = [(i, f'name{i % 7}', f'code{i}'.ljust(60, '-'), 'x' * 250) for i in range(1, 121)]
+        commands = ['create table t (id integer primary key, name text, code text unique, note text)',
+                    'create index t_name on t(name)']
+        commands += [f"insert into t values ({i}, '{name}', '{code}', '{note}')" for i, name, code, note in rows]
+        commands += ['.exit']
+        db_file = root_path + 'migrate_integer_keys_format.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        run(commands, cmd_args)
+        with open(db_file, 'rb') as f:
+            image = bytearray(f.read())
+
+        page_size = 4096
+        u64 = lambda offset: int.from_bytes(image[offset:offset + 8], 'little')
+        u16 = lambda offset: int.from_bytes(image[offset:offset + 2], 'little')
+        put = lambda offset, value, size=8: image.__setitem__(slice(offset, offset + size), value.to_bytes(size, 'little'))
+
+        # Roots of the tables and indexes, from the catalog leaf on page 1:
+        # each record holds a name, then the root page as an integer
+        roots = []
+        for cell_num in range(u64(page_size + 10)):
+            offset = page_size + u16(page_size + 34 + 2 * cell_num) + 12
+            offset += 5 + int.from_bytes(image[offset + 1:offset + 5], 'little')
+            width = {1: 1, 2: 2, 3: 4, 4: 8}[image[offset]]
+            roots.append(int.from_bytes(image[offset + 1:offset + 1 + width], 'little'))
+
+        next_key = 1
+        for root in roots:
+            internal_pages = []
+            leaves = []
+            def walk(page_num):
+                base = page_num * page_size
+                if image[base] != 0:
+                    leaves.append(page_num)
+                    return
+                internal_pages.append(page_num)
+                for cell_num in range(u64(base + 10)):
+                    walk(u64(base + u16(base + 34 + 2 * cell_num)))
+                walk(u64(base + 18))
+            walk(root)
+
+            level = []
+            for leaf in leaves:
+                base = leaf * page_size
+                num_cells = u64(base + 10)
+                if image[base] == 2:
+                    # An index leaf: cells of a hash key and an empty record
+                    keys = range(next_key, next_key + num_cells)
+                    next_key += num_cells
+                    next_leaf = u64(base + 18)
+                    image[base:base + page_size] = bytes(page_size)
+                    image[base] = 1
+                    put(base + 10, num_cells)
+                    put(base + 18, next_leaf)
+                    put(base + 26, page_size - 12 * num_cells)
+                    for cell_num, key in enumerate(keys):
+                        offset = page_size - 12 * (cell_num + 1)
+                        put(base + 34 + 2 * cell_num, offset, 2)
+                        put(base + offset, key)
+                level.append((leaf, u64(base + u16(base + 34 + 2 * (num_cells - 1)))))
+            # Levels of up to four children each, the top one on the root page
+            # and the others on the pages of the internal nodes they replace
+            while len(level) > 1:
+                num_nodes = (len(level) + 3) // 4
+                groups = [level[len(level) * i // num_nodes:len(level) * (i + 1) // num_nodes] for i in range(num_nodes)]
+                parents = []
+                for group in groups:
+                    if num_nodes == 1:
+                        page_num = root
+                    elif len(internal_pages) > 1:
+                        page_num = internal_pages.pop()
+                    else:
+                        page_num = len(image) // page_size
+                        image += bytes(page_size)
+                    base = page_num * page_size
+                    image[base:base + page_size] = bytes(page_size)
+                    image[base + 1] = num_nodes == 1
+                    put(base + 10, len(group) - 1)
+                    put(base + 18, group[-1][0])
+                    for cell_num, (child, key) in enumerate(group[:-1]):
+                        put(base + 26 + 16 * cell_num, child)
+                        put(base + 34 + 16 * cell_num, key)
+                    for child, _ in group:
+                        put(child * page_size + 2, page_num)
+                    parents.append((page_num, group[-1][1]))
+                level = parents
+        put(32, len(image) // page_size)
+        put(16, 6, 4)
+        with open(db_file, 'wb') as f:
+            f.write(image)
+
+        name = rows[40][1]
+        code = rows[56][2]
+        commands = ['.stats t',
+                    'select id, name from t',
+                    f"select id from t where name = '{name}'",
+                    f"select id, name from t where code = '{code}'",
+                    f"insert into t values (200, 'other', '{code}', null)",
+                    "insert into t values (200, 'other', 'code200', null)",
+                    "select id from t where name = 'other'",
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == 'db > Depth: 4\ndb > ' + ''.join(f'{i} "{row_name}"\n' for i, row_name, _, _ in rows) + 'Executed.\n' + \
+            'db > ' + ''.join(f'{i}\n' for i, row_name, _, _ in rows if row_name == name) + 'Executed.\n' + \
+            'db > 57 "name1"\nExecuted.\n' + \
+            f'db > Error: Unique column code already holds "{code}".\n' + \
+            'db > Executed.\ndb > 200\nExecuted.\ndb > '
+        with open(db_file, 'rb') as f:
+            f.seek(16)
+            assert int.from_bytes(f.read(4), 'little') == 7
+    finally:
+        os.remove(db_file)
+
+def test_create_index():
+    global db_file
+    try:
+        commands = [f'insert {i} user{i} person{i % 5}@example.com' for i in range(1, 31)]
+        commands += ['create index byemail on users(email)',
+                     'create index byemail on users(username)',
+                     'create index users on users(email)',
+                     'create index byname on books(title)',
+                     'create index byname on users(title)',
+                     'create index unique on users(email)',
+                     "select id from users where email = 'person2@example.com'",
+                     'update 7 set email=person0@example.com',
+                     'delete 12',
+                     'insert 31 user31 person2@example.com',
+                     "select id, username from users where email = 'person2@example.com' order by id desc limit 3",
+                     "select count(*) from users where email = 'person0@example.com' and id > 10",
+                     '.btree byemail',
+                     '.exit']
+        db_file = root_path + 'create_index.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        tree = out.split('db > ')[-2]
+        assert tree.startswith('Tree: \n- leaf (size 30)\n ("person0@example.com", 5)\n ("person0@example.com", 7)\n')
+        assert out.replace(tree, '') == 'db > Executed.\n' * 31 + \
+            """db > Error: Index byemail already exists.
+db > Error: Table users already exists.
+db > Error: No such table: books.
+db > Error: No such column: title.
+db > Syntax error at position 14: expected an index name, found 'unique'.
+db > 2
+7
+12
+17
+22
+27
+Executed.
 db > Executed.
 db > Executed.
 db > Executed.
+db > 31 "user31"
+27 "user27"
+22 "user22"
+Executed.
+db > 4
+Executed.
+db > db > """
+
+        # The index survives a reopen; one created in a rolled back
+        # transaction does not
+        commands = ['begin',
+                    'create index byname on users(username)',
+                    "select id from users where username = 'user3'",
+                    'rollback',
+                    '.btree byname',
+                    "select id from users where email = 'person2@example.com'",
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == """db > Executed.
 db > Executed.
+db > 3
+Executed.
 db > Executed.
+db > Error: No such table: byname.
+db > 2
+17
+22
+27
+31
+Executed.
+db > """
+
+        # Long values are indexed by their start; those sharing it are told
+        # apart by the whole value
+        prefix = 'a' * 600
+        commands = ['create table notes (id integer primary key, body text)',
+                    f"insert into notes values (1, '{prefix}x')",
+                    f"insert into notes values (2, '{prefix}y')",
+                    'create index notes_body on notes(body)',
+                    f"insert into notes values (3, '{prefix}x')",
+                    f"select id from notes where body = '{prefix}x'",
+                    f"select id from notes where body = '{prefix}'",
+                    'delete from notes where id = 1',
+                    f"select id from notes where body = '{prefix}x'",
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 5 + """db > 1
+3
+Executed.
 db > Executed.
 db > Executed.
+db > 3
+Executed.
+db > """
+    finally:
+        os.remove(db_file)
+
+def test_unique():
+    global db_file
+    try:
+        commands = ['create table people (id integer primary key, email text unique, name text)',
+                    "insert into people values (1, 'ada@example.com', 'Ada')",
+                    "insert into people values (2, 'ada@example.com', 'Bob')",
+                    "insert into people values (2, null, 'Bob')",
+                    "insert into people values (3, null, 'Cy')",
+                    "update people set email = 'ada@example.com' where id = 2",
+                    "update people set email = 'ada@example.com' where id = 1",
+                    "update people set email = 'bob@example.com' where id = 2",
+                    "select * from people where email = 'bob@example.com'",
+                    'insert 1 user1 person1@example.com',
+                    'insert 2 user2 person1@example.com',
+                    'create unique index users_email on users(email)',
+                    'delete 2',
+                    'create unique index users_email on users(email)',
+                    '.exit']
+        db_file = root_path + 'unique.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == """db > Executed.
 db > Executed.
+db > Error: Unique column email already holds "ada@example.com".
 db > Executed.
 db > Executed.
+db > Error: Unique column email already holds "ada@example.com".
 db > Executed.
 db > Executed.
+db > 2 "bob@example.com" "Bob"
+Executed.
 db > Executed.
 db > Executed.
+db > Error: Column email holds "person1@example.com" more than once.
 db > Executed.
 db > Executed.
+db > """
+
+        # The constraints hold after a reopen
+        commands = ["insert into people values (4, 'bob@example.com', 'Dee')",
+                    'insert 3 user3 person1@example.com',
+                    "insert into people values (4, 'dee@example.com', 'Dee')",
+                    'select id from people',
+                    '.exit']
+        out = run(commands, cmd_args)
+        assert out == """db > Error: Unique column email already holds "bob@example.com".
+db > Error: Unique column email already holds "person1@example.com".
 db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > Executed.
-db > 1 "user1" "person1@example.com"
-2 "user2" "person2@example.com"
-3 "user3" "person3@example.com"
-4 "user4" "person4@example.com"
-5 "user5" "person5@example.com"
-6 "user6" "person6@example.com"
-7 "user7" "person7@example.com"
-8 "user8" "person8@example.com"
-9 "user9" "person9@example.com"
-10 "user10" "person10@example.com"
-11 "user11" "person11@example.com"
-12 "user12" "person12@example.com"
-13 "user13" "person13@example.com"
-14 "user14" "person14@example.com"
-15 "user15" "person15@example.com"
-16 "user16" "person16@example.com"
-17 "user17" "person17@example.com"
-18 "user18" "person18@example.com"
-19 "user19" "person19@example.com"
-20 "user20" "person20@example.com"
-21 "user21" "person21@example.com"
-22 "user22" "person22@example.com"
-23 "user23" "person23@example.com"
-24 "user24" "person24@example.com"
-25 "user25" "person25@example.com"
-26 "user26" "person26@example.com"
-27 "user27" "person27@example.com"
-28 "user28" "person28@example.com"
-29 "user29" "person29@example.com"
-30 "user30" "person30@example.com"
-31 "user31" "person31@example.com"
-32 "user32" "person32@example.com"
-33 "user33" "person33@example.com"
-34 "user34" "person34@example.com"
-35 "user35" "person35@example.com"
-36 "user36" "person36@example.com"
-37 "user37" "person37@example.com"
-38 "user38" "person38@example.com"
-39 "user39" "person39@example.com"
-40 "user40" "person40@example.com"
-41 "user41" "person41@example.com"
-42 "user42" "person42@example.com"
-43 "user43" "person43@example.com"
-44 "user44" "person44@example.com"
-45 "user45" "person45@example.com"
-46 "user46" "person46@example.com"
-47 "user47" "person47@example.com"
-48 "user48" "person48@example.com"
-49 "user49" "person49@example.com"
-50 "user50" "person50@example.com"
-51 "user51" "person51@example.com"
-52 "user52" "person52@example.com"
-53 "user53" "person53@example.com"
-54 "user54" "person54@example.com"
-55 "user55" "person55@example.com"
-56 "user56" "person56@example.com"
-57 "user57" "person57@example.com"
-58 "user58" "person58@example.com"
-59 "user59" "person59@example.com"
-60 "user60" "person60@example.com"
-61 "user61" "person61@example.com"
-62 "user62" "person62@example.com"
-63 "user63" "person63@example.com"
-64 "user64" "person64@example.com"
-65 "user65" "person65@example.com"
-66 "user66" "person66@example.com"
-67 "user67" "person67@example.com"
-68 "user68" "person68@example.com"
-69 "user69" "person69@example.com"
-70 "user70" "person70@example.com"
-71 "user71" "person71@example.com"
-72 "user72" "person72@example.com"
-73 "user73" "person73@example.com"
-74 "user74" "person74@example.com"
-75 "user75" "person75@example.com"
-76 "user76" "person76@example.com"
-77 "user77" "person77@example.com"
-78 "user78" "person78@example.com"
-79 "user79" "person79@example.com"
-80 "user80" "person80@example.com"
-81 "user81" "person81@example.com"
-82 "user82" "person82@example.com"
-83 "user83" "person83@example.com"
-84 "user84" "person84@example.com"
-85 "user85" "person85@example.com"
-86 "user86" "person86@example.com"
-87 "user87" "person87@example.com"
-88 "user88" "person88@example.com"
-89 "user89" "person89@example.com"
-90 "user90" "person90@example.com"
-91 "user91" "person91@example.com"
-92 "user92" "person92@example.com"
-93 "user93" "person93@example.com"
-94 "user94" "person94@example.com"
-95 "user95" "person95@example.com"
-96 "user96" "person96@example.com"
-97 "user97" "person97@example.com"
-98 "user98" "person98@example.com"
-99 "user99" "person99@example.com"
+db > 1
+2
+3
+4
 Executed.
-db > Tree: 
-- internal (size 1)
- - internal (size 1)
-  - internal (size 1)
-   - leaf (size 7)
-    1
-    2
-    3
-    4
-    5
-    6
-    7
-   - key 7
-   - leaf (size 7)
-    8
-    9
-    10
-    11
-    12
-    13
-    14
-  - key 14
-  - internal (size 1)
-   - leaf (size 7)
-    15
-    16
-    17
-    18
-    19
-    20
-    21
-   - key 21
-   - leaf (size 7)
-    22
-    23
-    24
-    25
-    26
-    27
-    28
- - key 28
- - internal (size 3)
-  - internal (size 1)
-   - leaf (size 7)
-    29
-    30
-    31
-    32
-    33
-    34
-    35
-   - key 35
-   - leaf (size 7)
-    36
-    37
-    38
-    39
-    40
-    41
-    42
-  - key 42
-  - internal (size 1)
-   - leaf (size 7)
-    43
-    44
-    45
-    46
-    47
-    48
-    49
-   - key 49
-   - leaf (size 7)
-    50
-    51
-    52
-    53
-    54
-    55
-    56
-  - key 56
-  - internal (size 1)
-   - leaf (size 7)
-    57
-    58
-    59
-    60
-    61
-    62
-    63
-   - key 63
-   - leaf (size 7)
-    64
-    65
-    66
-    67
-    68
-    69
-    70
-  - key 70
-  - internal (size 3)
-   - leaf (size 7)
-    71
-    72
-    73
-    74
-    75
-    76
-    77
-   - key 77
-   - leaf (size 7)
-    78
-    79
-    80
-    81
-    82
-    83
-    84
-   - key 84
-   - leaf (size 7)
-    85
-    86
-    87
-    88
-    89
-    90
-    91
-   - key 91
-   - leaf (size 8)
-    92
-    93
-    94
-    95
-    96
-    97
-    98
-    99
-db > '''
+db > """
+    finally:
+        os.remove(db_file)
+
+def test_page_size():
+    global db_file
+    try:
+        commands = []
+        for i in range(1, 1501):
+            commands.append(f"insert {i} user{i} person{i}@example.com")
+        commands.append('create index users_email on users(email)')
+        commands.append('.stats users')
+        commands.append('.exit')
+        db_file = root_path + 'page_size.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        cmd_args.append('--page-size')
+        cmd_args.append('512')
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 1501 + 'db > Depth: 4\ndb > '
+        with open(db_file, 'rb') as f:
+            data = f.read()
+        assert int.from_bytes(data[20:24], 'little') == 512
+        assert len(data) % 512 == 0
+
+        # An existing file keeps its page size whatever the flag asks for
+        commands = ["select id from users where email = 'person777@example.com'",
+                    'select count(*) from users',
+                    '.stats users',
+                    '.exit']
+        cmd_args = [execute_file, db_file, '--page-size', '65536']
+        out = run(commands, cmd_args)
+        assert out == """db > 777
+Executed.
+db > 1500
+Executed.
+db > Depth: 4
+db > """
+        with open(db_file, 'rb') as f:
+            assert int.from_bytes(f.read(24)[20:24], 'little') == 512
     finally:
         os.remove(db_file)
 
+    try:
+        body = 'x' * 200000
+        commands = ['create table docs (id integer primary key, body text)',
+                    f"insert into docs values (1, '{body}')",
+                    '.exit']
+        db_file = root_path + 'page_size_large.db'
+        cmd_args = [execute_file, db_file, '--page-size', '65536']
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\ndb > Executed.\ndb > '
+        assert os.path.getsize(db_file) % 65536 == 0
+
+        commands = ['select body from docs', '.exit']
+        out = run(commands, [execute_file, db_file])
+        assert out == f'db > "{body}"\nExecuted.\ndb > '
+    finally:
+        os.remove(db_file)
+
+    db_file = root_path + 'page_size_invalid.db'
+    for page_size in ['1000', '256', '131072']:
+        out = run(['.exit'], [execute_file, db_file, '--page-size', page_size])
+        assert out == f'Error: Page size {page_size} is not a power of two from 512 to 65536.\n'
+        assert not os.path.exists(db_file)
+
+def test_import():
+    global db_file
+    csv_file = root_path + 'import.csv'
+    try:
+        with open(csv_file, 'w') as f:
+            for i in range(3000, 0, -1):
+                f.write(f'{i},user{i},person{i % 7}@example.com\n')
+        commands = ['create index users_email on users(email)',
+                    f'.import {csv_file} users',
+                    'select count(*) from users',
+                    'select id, username from users where id = 1234',
+                    "select count(*) from users where email = 'person3@example.com'",
+                    '.stats users',
+                    '.stats users_email',
+                    '.exit']
+        db_file = root_path + 'import.db'
+        cmd_args = []
+        cmd_args.append(execute_file)
+        cmd_args.append(db_file)
+        out = run(commands, cmd_args)
+        assert out == """db > Executed.
+db > Imported 3000 rows.
+db > 3000
+Executed.
+db > 1234 "user1234"
+Executed.
+db > 429
+Executed.
+db > Depth: 2
+db > Depth: 2
+db > """
+
+        # The bulk-built trees take inserts and deletes like any other
+        commands = [f'delete {i}' for i in range(1, 2901)]
+        commands += ['insert 5000 user5000 person3@example.com',
+                     'select count(*) from users',
+                     "select count(*) from users where email = 'person3@example.com'",
+                     '.stats users',
+                     '.exit']
+        out = run(commands, cmd_args)
+        assert out == 'db > Executed.\n' * 2901 + """db > 101
+Executed.
+db > 16
+Executed.
+db > Depth: 2
+db > """
+
+        # Quoted fields, NULLs and errors naming the line
+        with open(csv_file, 'w') as f:
+            f.write('6001,"Ada, Jr.","ada ""the first""@example.com"\n\n6002,,bob@example.com\n')
+        commands = [f'.import {csv_file} users',
+                    'select * from users where id >= 6001']
+        with open(csv_file + '2', 'w') as f:
+            f.write('6003,cy,cy@example.com\n6004,dee\n')
+        commands += [f'.import {csv_file}2 users',
+                     f'.import {csv_file} users',
+                     f'.import {csv_file} books',
+                     'select count(*) from users',
+                     '.exit']
+        out = run(commands, cmd_args)
+        assert out == """db > Imported 2 rows.
+db > 6001 "Ada, Jr." "ada \\"the first\\"@example.com"
+6002 NULL "bob@example.com"
+Executed.
+db > Error: Line 2: Expected 3 values, found 2.
+db > Error: Line 1: Duplicate key.
+db > Error: No such table: books.
+db > 103
+Executed.
+db > """
+    finally:
+        os.remove(db_file)
+        for f in [csv_file, csv_file + '2']:
+            if os.path.exists(f):
+                os.remove(f)
+
 test_insert()
 test_insert_too_long()
 test_insert_exit_select_exit()
@@ -582,4 +1553,26 @@ test_bt()
 test_bs_dup()
 test_split()
 test_split_internal()
-
+test_buffer_pool_eviction()
+test_delete()
+test_delete_merge()
+test_update()
+test_free_list()
+test_foreign_file()
+test_corrupt_page()
+test_crash_recovery()
+test_transaction()
+test_sql_statements()
+test_select_where()
+test_select_order_limit()
+test_aggregates()
+test_create_table()
+test_typed_columns()
+test_slotted_leaf()
+test_overflow_pages()
+test_migrate_native_format()
+test_migrate_integer_keys_format()
+test_create_index()
+test_unique()
+test_page_size()
+test_import()